@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::custom_errors::AsmError;
+
+const START: u16 = 0x200;
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_CONSTANT_DEPTH: usize = 32;
+
+// Where a line comes from, for the error messages
+#[derive(Clone)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+// A piece of a line and the column where it starts
+#[derive(Clone)]
+struct Token {
+    text: String,
+    column: usize,
+}
+
+impl Token {
+    // The token without its first `start` bytes, trimmed
+    fn from(&self, start: usize) -> Token {
+        let rest = &self.text[start..];
+        let trimmed = rest.trim_start();
+        Token {
+            text: trimmed.trim_end().to_string(),
+            column: self.column + start + rest.len() - trimmed.len(),
+        }
+    }
+
+    fn is(&self, word: &str) -> bool {
+        self.text.eq_ignore_ascii_case(word)
+    }
+
+    fn register(&self) -> Option<u8> {
+        let mut chars = self.text.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('V' | 'v'), Some(digit), None) => digit.to_digit(16).map(|x| x as u8),
+            _ => None,
+        }
+    }
+}
+
+// A line that produces bytes, encoded once all the labels are known
+struct Statement {
+    location: Location,
+    mnemonic: Token,
+    operands: Vec<Token>,
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, (Token, Location)>,
+    adress: usize,
+}
+
+/// Assembles the file at `path` into a rom that is loaded at 0x200.
+///
+/// One instruction per line with the mnemonics of the disassembler (`LD V3, 0x12`,
+/// `DRW V0, V1, 5`, `LD I, LONG 0x1234`...), `;` starts a comment. The other lines are
+/// - `name:` a label, an instruction can follow it on the same line
+/// - `NAME EQU 12` a constant
+/// - `DB 0xF0, 0x90` bytes and `DW 0x1234` big endian words
+/// - `INCLUDE "file.asm"` the lines of another file, relative to this one
+///
+/// The numbers are decimal, hexadecimal (`0x1F`, `$1F` or `#1F`) or binary (`0b1010`),
+/// they can be added and subtracted with labels and constants (`sprite + 5`).
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: format!("Could not read the file : {err}"),
+    })?;
+    assemble(&source, path)
+}
+
+/// Same as `assemble_file` for a source that is already read, `path` is used for the
+/// error messages and to find the included files.
+pub fn assemble(source: &str, path: &Path) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        statements: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        adress: START as usize,
+    };
+    assembler.read(source, path, 0)?;
+
+    let mut rom = Vec::new();
+    for statement in &assembler.statements {
+        assembler.encode(statement, &mut rom)?;
+    }
+    Ok(rom)
+}
+
+impl Assembler {
+    // FIRST PASS : the labels, constants and included files
+
+    fn read(&mut self, source: &str, path: &Path, depth: usize) -> Result<(), AsmError> {
+        for (i, line) in source.lines().enumerate() {
+            let location = Location {
+                file: path.display().to_string(),
+                line: i + 1,
+            };
+            let code = line.split(';').next().unwrap_or("");
+            let mut rest = Token {
+                text: code.to_string(),
+                column: 1,
+            }
+            .from(0);
+
+            // Label
+            let first_word = rest.text.split_whitespace().next().unwrap_or("");
+            if let Some(label) = first_word.strip_suffix(':') {
+                check_name(label, &location, rest.column)?;
+                let adress = self.adress as u16;
+                if self.labels.insert(label.to_string(), adress).is_some() {
+                    return Err(location.error(rest.column, format!("{label} is already defined")));
+                }
+                rest = rest.from(first_word.len());
+            }
+            if rest.text.is_empty() {
+                continue;
+            }
+
+            // Mnemonic and operands
+            let mnemonic_len = rest
+                .text
+                .find(char::is_whitespace)
+                .unwrap_or(rest.text.len());
+            let mnemonic = Token {
+                text: rest.text[..mnemonic_len].to_string(),
+                column: rest.column,
+            };
+            let rest = rest.from(mnemonic_len);
+
+            // Constant
+            let second_word = rest.text.split_whitespace().next().unwrap_or("");
+            if second_word.eq_ignore_ascii_case("equ") {
+                check_name(&mnemonic.text, &location, mnemonic.column)?;
+                let value = rest.from(second_word.len());
+                if value.text.is_empty() {
+                    return Err(location.error(value.column, "Missing value"));
+                }
+                let name = mnemonic.text.clone();
+                if self
+                    .constants
+                    .insert(name, (value, location.clone()))
+                    .is_some()
+                {
+                    return Err(location.error(
+                        mnemonic.column,
+                        format!("{} is already defined", mnemonic.text),
+                    ));
+                }
+                continue;
+            }
+
+            let operands = split_operands(&rest, &location)?;
+            if mnemonic.is("include") {
+                self.include(&operands, &location, path, depth)?;
+                continue;
+            }
+
+            self.adress += if mnemonic.is("db") {
+                operands.len()
+            } else if mnemonic.is("dw") {
+                2 * operands.len()
+            } else if mnemonic.is("ld") && operands.len() == 2 && long(&operands[1]).is_some() {
+                4
+            } else {
+                2
+            };
+            self.statements.push(Statement {
+                location,
+                mnemonic,
+                operands,
+            });
+        }
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        operands: &[Token],
+        location: &Location,
+        path: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let [file] = operands else {
+            return Err(location.error(1, "INCLUDE takes a file name"));
+        };
+        let name = file
+            .text
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .ok_or_else(|| location.error(file.column, "The file name must be between quotes"))?;
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(location.error(file.column, "Too many nested includes"));
+        }
+        let included = path.parent().unwrap_or(Path::new("")).join(name);
+        let source = fs::read_to_string(&included)
+            .map_err(|err| location.error(file.column, format!("Could not read {name} : {err}")))?;
+        self.read(&source, &included, depth + 1)
+    }
+
+    // SECOND PASS : the bytes
+
+    fn encode(&self, statement: &Statement, rom: &mut Vec<u8>) -> Result<(), AsmError> {
+        let Statement {
+            location,
+            mnemonic,
+            operands,
+        } = statement;
+        let error = |column: usize, message: &str| location.error(column, message);
+
+        // Operands
+        let count = |n: usize| -> Result<(), AsmError> {
+            if operands.len() == n {
+                Ok(())
+            } else {
+                let s = if n == 1 { "" } else { "s" };
+                Err(error(
+                    mnemonic.column,
+                    &format!("{} takes {n} operand{s}", mnemonic.text),
+                ))
+            }
+        };
+        let register = |i: usize| -> Result<u16, AsmError> {
+            match operands[i].register() {
+                Some(x) => Ok(x as u16),
+                None => Err(error(operands[i].column, "Expected a register (V0 to VF)")),
+            }
+        };
+        let number = |token: &Token, max: i64| -> Result<u16, AsmError> {
+            let value = self.value(token, location, 0)?;
+            match value {
+                // Negative bytes are written in two's complement
+                _ if max == 0xFF && (-0x80..0).contains(&value) => Ok((value & 0xFF) as u16),
+                _ if (0..=max).contains(&value) => Ok(value as u16),
+                _ => Err(error(
+                    token.column,
+                    &format!("{value} doesn't fit, the maximum is 0x{max:X}"),
+                )),
+            }
+        };
+        let nibble = |i: usize| number(&operands[i], 0xF);
+        let byte = |i: usize| number(&operands[i], 0xFF);
+        let adress = |i: usize| number(&operands[i], 0xFFF);
+
+        let name = mnemonic.text.to_ascii_uppercase();
+        let x = || register(0).map(|x| x << 8);
+        let xy = || -> Result<u16, AsmError> {
+            count(2)?;
+            Ok(register(0)? << 8 | register(1)? << 4)
+        };
+
+        let instruction = match name.as_str() {
+            "DB" => {
+                for i in 0..operands.len() {
+                    rom.push(byte(i)? as u8);
+                }
+                return Ok(());
+            }
+            "DW" => {
+                for token in operands {
+                    rom.extend(number(token, 0xFFFF)?.to_be_bytes());
+                }
+                return Ok(());
+            }
+            "CLS" => count(0).map(|_| 0x00E0)?,
+            "RET" => count(0).map(|_| 0x00EE)?,
+            "SCD" => count(1).and_then(|_| nibble(0)).map(|n| 0x00C0 | n)?,
+            "SCU" => count(1).and_then(|_| nibble(0)).map(|n| 0x00D0 | n)?,
+            "SCR" => count(0).map(|_| 0x00FB)?,
+            "SCL" => count(0).map(|_| 0x00FC)?,
+            "EXIT" => count(0).map(|_| 0x00FD)?,
+            "LOW" => count(0).map(|_| 0x00FE)?,
+            "HIGH" => count(0).map(|_| 0x00FF)?,
+            "SYS" => count(1).and_then(|_| adress(0))?,
+            "JP" if operands.len() == 2 => match register(0)? {
+                0 => 0xB000 | adress(1)?,
+                _ => 0xB000 | x()? | byte(1)?, // BXNN
+            },
+            "JP" => count(1).and_then(|_| adress(0)).map(|nnn| 0x1000 | nnn)?,
+            "CALL" => count(1).and_then(|_| adress(0)).map(|nnn| 0x2000 | nnn)?,
+            "SE" | "SNE" => {
+                count(2)?;
+                let (byte_opcode, register_opcode) = if name == "SE" {
+                    (0x3000, 0x5000)
+                } else {
+                    (0x4000, 0x9000)
+                };
+                match operands[1].register() {
+                    Some(_) => register_opcode | xy()?,
+                    None => byte_opcode | x()? | byte(1)?,
+                }
+            }
+            "SAVE" => 0x5002 | xy()?,
+            "LOAD" => 0x5003 | xy()?,
+            "ADD" => {
+                count(2)?;
+                if operands[0].is("i") {
+                    0xF01E | register(1)? << 8
+                } else if operands[1].register().is_some() {
+                    0x8004 | xy()?
+                } else {
+                    0x7000 | x()? | byte(1)?
+                }
+            }
+            "OR" => 0x8001 | xy()?,
+            "AND" => 0x8002 | xy()?,
+            "XOR" => 0x8003 | xy()?,
+            "SUB" => 0x8005 | xy()?,
+            "SUBN" => 0x8007 | xy()?,
+            "SHR" | "SHL" => {
+                let opcode = if name == "SHR" { 0x8006 } else { 0x800E };
+                match operands.len() {
+                    1 => opcode | x()? | register(0)? << 4, // VY is VX for the OLD quirk
+                    _ => opcode | xy()?,
+                }
+            }
+            "RND" => count(2).and_then(|_| Ok(0xC000 | x()? | byte(1)?))?,
+            "DRW" => count(3)
+                .and_then(|_| Ok(0xD000 | register(0)? << 8 | register(1)? << 4 | nibble(2)?))?,
+            "SKP" => count(1).and_then(|_| x()).map(|x| 0xE09E | x)?,
+            "SKNP" => count(1).and_then(|_| x()).map(|x| 0xE0A1 | x)?,
+            "PLANE" => count(1).and_then(|_| nibble(0)).map(|n| 0xF001 | n << 8)?,
+            "AUDIO" => count(0).map(|_| 0xF002)?,
+            "PITCH" => count(1).and_then(|_| x()).map(|x| 0xF03A | x)?,
+            "LD" => {
+                count(2)?;
+                let (destination, source) = (&operands[0], &operands[1]);
+                let special = |token: &Token| {
+                    ["I", "DT", "ST", "F", "HF", "B", "[I]", "R", "K"]
+                        .into_iter()
+                        .find(|name| token.is(name))
+                };
+                match (special(destination), special(source)) {
+                    (Some("I"), _) => match long(source) {
+                        Some(nnnn) => {
+                            let nnnn = number(&nnnn, 0xFFFF)?;
+                            rom.extend([0xF0, 0x00]);
+                            rom.extend(nnnn.to_be_bytes());
+                            return Ok(());
+                        }
+                        None => 0xA000 | adress(1)?,
+                    },
+                    (Some("DT"), _) => 0xF015 | register(1)? << 8,
+                    (Some("ST"), _) => 0xF018 | register(1)? << 8,
+                    (Some("F"), _) => 0xF029 | register(1)? << 8,
+                    (Some("HF"), _) => 0xF030 | register(1)? << 8,
+                    (Some("B"), _) => 0xF033 | register(1)? << 8,
+                    (Some("[I]"), _) => 0xF055 | register(1)? << 8,
+                    (Some("R"), _) => 0xF075 | register(1)? << 8,
+                    (_, Some("DT")) => 0xF007 | x()?,
+                    (_, Some("K")) => 0xF00A | x()?,
+                    (_, Some("[I]")) => 0xF065 | x()?,
+                    (_, Some("R")) => 0xF085 | x()?,
+                    _ if source.register().is_some() => 0x8000 | xy()?,
+                    _ => 0x6000 | x()? | byte(1)?,
+                }
+            }
+            _ => {
+                return Err(error(
+                    mnemonic.column,
+                    &format!("Unknown instruction: {}", mnemonic.text),
+                ))
+            }
+        };
+        rom.extend(instruction.to_be_bytes());
+        Ok(())
+    }
+
+    // Value of a number, label or constant, or a sum of them
+    fn value(&self, token: &Token, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        let text = &token.text;
+        let mut total = 0;
+        let mut sign = 1;
+        let mut start = 0;
+        for (i, c) in text.char_indices().chain([(text.len(), '+')]) {
+            if c != '+' && c != '-' {
+                continue;
+            }
+            let term = Token {
+                text: text[start..i].to_string(),
+                column: token.column + start,
+            }
+            .from(0);
+            if !term.text.is_empty() {
+                total += sign * self.term(&term, location, depth)?;
+            } else if start != 0 || i == text.len() {
+                // Only a sign can come before the first term
+                return Err(location.error(term.column, "Missing value"));
+            }
+            sign = if c == '-' { -1 } else { 1 };
+            start = i + 1;
+        }
+        Ok(total)
+    }
+
+    fn term(&self, term: &Token, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        let text = term.text.as_str();
+        let lowercase = text.to_ascii_lowercase();
+        let number = if let Some(hex) = lowercase
+            .strip_prefix("0x")
+            .or_else(|| lowercase.strip_prefix('$'))
+            .or_else(|| lowercase.strip_prefix('#'))
+        {
+            Some(i64::from_str_radix(hex, 16))
+        } else if let Some(binary) = lowercase.strip_prefix("0b") {
+            Some(i64::from_str_radix(binary, 2))
+        } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+            Some(text.parse())
+        } else {
+            None
+        };
+        if let Some(number) = number {
+            return number
+                .map_err(|_| location.error(term.column, format!("Invalid number: {text}")));
+        }
+
+        if let Some(adress) = self.labels.get(text) {
+            Ok(*adress as i64)
+        } else if let Some((value, constant_location)) = self.constants.get(text) {
+            if depth >= MAX_CONSTANT_DEPTH {
+                return Err(location.error(term.column, format!("{text} is defined with itself")));
+            }
+            self.value(value, constant_location, depth + 1)
+        } else {
+            Err(location.error(term.column, format!("Unknown label or constant: {text}")))
+        }
+    }
+}
+
+// The adress of `LD I, LONG 0x1234` (XO-CHIP F000 NNNN)
+fn long(operand: &Token) -> Option<Token> {
+    let first_word = operand.text.split_whitespace().next()?;
+    first_word
+        .eq_ignore_ascii_case("long")
+        .then(|| operand.from(first_word.len()))
+}
+
+// The operands separated by commas
+fn split_operands(rest: &Token, location: &Location) -> Result<Vec<Token>, AsmError> {
+    if rest.text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut operands = Vec::new();
+    let mut start = 0;
+    for part in rest.text.split(',') {
+        let operand = Token {
+            text: part.to_string(),
+            column: rest.column + start,
+        }
+        .from(0);
+        if operand.text.is_empty() {
+            return Err(location.error(operand.column, "Missing operand"));
+        }
+        operands.push(operand);
+        start += part.len() + 1;
+    }
+    Ok(operands)
+}
+
+fn check_name(name: &str, location: &Location, column: usize) -> Result<(), AsmError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(location.error(column, format!("Invalid name: {name}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+        super::assemble(source, Path::new("test.asm"))
+    }
+
+    #[test]
+    fn the_labels_can_be_used_before_they_are_defined() {
+        let source = "
+start:  CALL sub        ; forward
+        JP end
+sub:    RET
+end:    JP start";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x22, 0x04, 0x12, 0x06, 0x00, 0xEE, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn the_constants_and_the_data_are_encoded() {
+        let source = "
+STEP  EQU SPEED + 1     ; defined with a later constant
+SPEED EQU $2
+      ADD V0, STEP
+      LD I, sprite + 1
+      LD I, LONG 0x1234
+sprite:
+      DB 0b11110000, #90, 255
+      DW 0xABCD";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x70, 0x03, 0xA2, 0x09, 0xF0, 0x00, 0x12, 0x34, 0xF0, 0x90, 0xFF, 0xAB, 0xCD]
+        );
+    }
+
+    #[test]
+    fn the_included_files_are_relative_to_the_including_one() {
+        let dir = env::temp_dir().join(format!("chip-asm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.asm"), "INCLUDE \"lib/sub.asm\"\nJP sub\n").unwrap();
+        fs::write(dir.join("lib/sub.asm"), "sub: LD V1, 7\n").unwrap();
+        let rom = assemble_file(&dir.join("main.asm"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rom.unwrap(), [0x61, 0x07, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn the_errors_give_the_line_and_the_column() {
+        let error = assemble("CLS\n   LD V0, nowhere").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test.asm:2:11: Unknown label or constant: nowhere"
+        );
+        let error = assemble("loop: JP loop\nloop: CLS").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(
+            assemble("  DRW V0, V1").unwrap_err().to_string(),
+            "test.asm:1:3: DRW takes 3 operands"
+        );
+    }
+}
@@ -1,56 +1,95 @@
-use std::{time::Duration, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use rodio::{Source, source::SineWave};
+use rodio::Source;
 
-struct ConditionalSineWave {
-    sine_wave: SineWave,
-    condition: Arc<AtomicBool>,
+const SAMPLE_RATE: u32 = 44100;
+const VOLUME: f32 = 0.25;
+
+/// What the game loop tells the audio thread, updated every frame.
+pub struct Beeper {
+    pub playing: AtomicBool,
+    pub pattern: Mutex<([u8; 16], f32)>, // 128 1-bit samples, played at this rate (samples per second)
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Beeper {
+            playing: AtomicBool::new(false),
+            pattern: Mutex::new(([0; 16], 0.0)),
+        }
+    }
+
+    pub fn update(&self, playing: bool, pattern: ([u8; 16], f32)) {
+        *self.pattern.lock().unwrap() = pattern;
+        self.playing.store(playing, Ordering::Relaxed);
+    }
 }
 
-impl ConditionalSineWave {
-    pub fn new(freq: f32, condition: Arc<AtomicBool>) -> Self {
-        ConditionalSineWave {
-            sine_wave: SineWave::new(freq),
-            condition,
+// Plays the audio pattern in loop when the beeper is on
+struct PatternWave {
+    beeper: Arc<Beeper>,
+    position: f32, // in bits of the pattern
+}
+
+impl PatternWave {
+    pub fn new(beeper: Arc<Beeper>) -> Self {
+        PatternWave {
+            beeper,
+            position: 0.0,
         }
     }
 }
 
-impl Source for ConditionalSineWave {
+impl Source for PatternWave {
     fn current_frame_len(&self) -> Option<usize> {
-        self.sine_wave.current_frame_len()
+        None
     }
 
     fn channels(&self) -> u16 {
-        self.sine_wave.channels()
+        1
     }
 
     fn sample_rate(&self) -> u32 {
-        self.sine_wave.sample_rate()
+        SAMPLE_RATE
     }
 
     fn total_duration(&self) -> Option<Duration> {
-        self.sine_wave.total_duration()
-    }    
+        None
+    }
 }
 
-impl Iterator for ConditionalSineWave {
+impl Iterator for PatternWave {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.condition.load(Ordering::Relaxed) {
-            self.sine_wave.next()
+        if !self.beeper.playing.load(Ordering::Relaxed) {
+            self.position = 0.0;
+            return Some(0.0);
+        }
+
+        let (pattern, rate) = *self.beeper.pattern.lock().unwrap();
+        let bit = self.position as usize % 128;
+        self.position = (self.position + rate / SAMPLE_RATE as f32) % 128.0;
+
+        if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+            Some(VOLUME)
         } else {
-            Some(0.0)
+            Some(-VOLUME)
         }
     }
 }
 
 // TODO : Fix underrun problem
-pub fn play_beep(condition: Arc<AtomicBool>) {
+pub fn play_beep(beeper: Arc<Beeper>) {
     let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
     let sink = rodio::Sink::try_new(&stream_handle).unwrap();
 
-    sink.append(ConditionalSineWave::new(440.0, condition));
+    sink.append(PatternWave::new(beeper));
     sink.sleep_until_end();
-}
\ No newline at end of file
+}
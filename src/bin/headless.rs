@@ -0,0 +1,111 @@
+// Runs a rom without window or sound, for automated tests :
+// cargo run --no-default-features --bin chip-headless -- --frames 120 --keys "60:+5 70:-5" <ROM>
+
+use chip::custom_errors::EmulatorError;
+use chip::debugger;
+use chip::headless::{self, Limit};
+use chip::launch_options::*;
+use chip::trace::Trace;
+use chip::Chip8;
+
+use std::{env, fs, process};
+
+const HEADLESS_USAGE: &str = "Usage: chip-headless [--frames <N> | --instructions <N>] [--keys <SCRIPT>] [--png <FILE>] [OPTIONS] <ROM>
+
+Runs the rom without window or sound, then prints the screen and the registers.
+
+  --frames <N>            Number of frames (60 per second) to run [default: 60]
+  --instructions <N>      Number of instructions to run instead
+  --keys <SCRIPT>         Keys to press and release, FRAME:+KEY or FRAME:-KEY (hexadecimal key)
+                          separated by spaces, for example \"10:+5 20:-5\"
+  --png <FILE>            Also write the screen in a PNG image
+  --no-text               Don't print the screen
+
+The other options are the ones of chip (--platform, --ips, --quirks...).";
+
+fn main() {
+    let mut limit = Limit::Frames(60);
+    let mut keys = Vec::new();
+    let mut png_path = None;
+    let mut text = true;
+
+    // Our options, the others are for LaunchOptions
+    let mut args = env::args().skip(1);
+    let mut other_args = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| exit(&format!("Missing value for {name}")))
+        };
+        let mut number = |name: &str| {
+            let n = value(name);
+            n.parse()
+                .unwrap_or_else(|_| exit(&format!("Invalid number for {name}: {n}")))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{HEADLESS_USAGE}");
+                return;
+            }
+            "--frames" => limit = Limit::Frames(number("--frames")),
+            "--instructions" => limit = Limit::Instructions(number("--instructions")),
+            "--keys" => {
+                keys = headless::parse_key_script(&value("--keys")).unwrap_or_else(|err| exit(&err))
+            }
+            "--png" => png_path = Some(value("--png")),
+            "--no-text" => text = false,
+            _ => other_args.push(arg),
+        }
+    }
+    let mut options = LaunchOptions::from_args(other_args).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2);
+    });
+
+    let rom = fs::read(&options.rom_path).unwrap_or_else(|err| {
+        let reason = format!("could not read {} : {err}", options.rom_path);
+        eprintln!("{}", EmulatorError::BadRom(reason));
+        process::exit(1);
+    });
+    // The keys come from --keys, the keymap is not needed
+    if let Err(err) = options.load_database(&rom) {
+        eprintln!("{err}");
+        process::exit(2);
+    }
+    let mut chip8 = Chip8::new(options.clone());
+    if let Err(err) = chip8.load_rom(&rom) {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+    if let Some(settings) = &options.trace {
+        let trace = Trace::new(settings).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        });
+        chip8.set_trace(Some(trace));
+    }
+
+    let result = headless::run(&mut chip8, limit, &keys);
+
+    if text {
+        print!("{}", chip8.screen().to_text());
+    }
+    println!("{}", debugger::state(&chip8));
+    println!("Instructions: {}", chip8.cycles());
+    if let Some(path) = png_path {
+        if let Err(err) = fs::write(&path, headless::screen_png(chip8.screen(), &options)) {
+            eprintln!("Could not write {path} : {err}");
+            process::exit(1);
+        }
+    }
+    if let Err(err) = result {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
+
+// Invalid arguments
+fn exit(message: &str) -> ! {
+    eprintln!("{message}\n\n{HEADLESS_USAGE}");
+    process::exit(2);
+}
@@ -0,0 +1,343 @@
+use crate::constants::*;
+use crate::custom_errors::EmulatorError;
+use crate::instructions;
+use crate::keys::{KeyWait, KeysState};
+use crate::launch_options::*;
+use crate::memory::{Memory, WatchHit, Watchpoint};
+use crate::opcodes::Opcode;
+use crate::savestate::Snapshot;
+use crate::screen::Screen;
+use crate::stack::Stack;
+use crate::trace::{Trace, TraceEntry};
+
+/// The whole CHIP-8 machine, without any window or sound device attached to it.
+pub struct Chip8 {
+    pc: u16,      // program counter
+    stack: Stack, // Stack of adresses used to call subroutines or return from them
+    memory: Memory,
+    screen: Screen,
+    keys_state: KeysState,
+    options: LaunchOptions,
+    cycles: u64,               // number of instructions executed since the start
+    waited: u64, // number of instructions not executed because of the display wait quirk
+    frames: u64, // number of timer ticks since the start
+    in_frame: bool, // run_frame_until stopped before the end of the frame
+    exited: bool, // 00FD was executed
+    watch_hits: Vec<WatchHit>, // of the last instruction
+    watch_log: Vec<WatchHit>, // of the watchpoints in log mode, until take_watch_log
+    trace: Option<Trace>,
+}
+
+impl Chip8 {
+    pub fn new(options: LaunchOptions) -> Chip8 {
+        let memory_size = if options.platform == Platform::XoChip {
+            XO_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        };
+        let mut chip8 = Chip8 {
+            pc: 0x200,
+            stack: Stack::new(options.quirks.stack_depth as usize),
+            memory: Memory::with_size(memory_size),
+            screen: Screen::new(),
+            keys_state: KeysState::new(),
+            options,
+            cycles: 0,
+            waited: 0,
+            frames: 0,
+            in_frame: false,
+            exited: false,
+            watch_hits: Vec::new(),
+            watch_log: Vec::new(),
+            trace: None,
+        };
+        chip8.load_font();
+        chip8
+    }
+
+    fn load_font(&mut self) {
+        self.options.fonts.load(&mut self.memory);
+    }
+
+    /// Copies the rom in memory at 0x200, where the execution starts.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmulatorError> {
+        self.memory.load_rom(rom)
+    }
+
+    // EXECUTION
+
+    /// Fetches, decodes and executes the instruction at `pc`.
+    /// Does nothing once the rom exited the interpreter.
+    /// On error `pc` stays at the instruction that failed, to show the state where it happened.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        if self.exited {
+            return Ok(());
+        }
+        let pc = self.pc;
+        // The reads done between the instructions (debugger...) don't trigger the watchpoints
+        self.memory.take_hits(pc);
+        // Taken before the execution, the instruction may overwrite itself
+        let traced = self.trace.as_ref().and_then(|trace| {
+            let instruction = self.memory.peek_word(pc);
+            trace
+                .filter()
+                .accepts(pc, instruction)
+                .then(|| (instruction, self.mnemonic(pc), self.registers()))
+        });
+        let result = instructions::decode(
+            &mut self.pc,
+            &mut self.stack,
+            &mut self.screen,
+            &mut self.memory,
+            &mut self.keys_state,
+            &mut self.exited,
+            &self.options,
+        );
+        self.watch_hits = self.memory.take_hits(pc);
+        self.watch_log
+            .extend(self.watch_hits.iter().filter(|hit| hit.log));
+        result.inspect_err(|_| self.pc = pc)?;
+        if let Some((instruction, mnemonic, before)) = traced {
+            self.record_trace(pc, instruction, mnemonic, before);
+        }
+        self.cycles += 1;
+        Ok(())
+    }
+
+    fn record_trace(&mut self, pc: u16, instruction: u16, mnemonic: String, before: [u8; 16]) {
+        let registers = (0..NB_REGISTERS)
+            .filter(|i| self.memory.read_register(*i) != before[*i])
+            .map(|i| (i as u8, before[i], self.memory.read_register(i)))
+            .collect();
+        let entry = TraceEntry {
+            cycle: self.cycles,
+            pc,
+            instruction,
+            mnemonic,
+            registers,
+            i: self.memory.read_adress(),
+            delay_timer: self.memory.read_delay_timer(),
+            sound_timer: self.memory.read_sound_timer(),
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.record(entry);
+        }
+    }
+
+    // The adress of F000 NNNN is in the next 2 bytes
+    fn mnemonic(&self, pc: u16) -> String {
+        match Opcode::decode(self.memory.peek_word(pc), self.options.platform) {
+            Some(Opcode::SetILong) => {
+                format!("LD I, 0x{:04X}", self.memory.peek_word(pc.wrapping_add(2)))
+            }
+            // The disassembler doesn't know the quirks and always writes BNNN with V0
+            Some(Opcode::JumpOffset(X, NNN)) if self.options.quirks.jumping => {
+                format!("JP V{X:X}, 0x{NNN:03X}")
+            }
+            Some(op) => op.to_string(),
+            None => "???".to_string(),
+        }
+    }
+
+    fn registers(&self) -> [u8; 16] {
+        std::array::from_fn(|i| self.memory.read_register(i))
+    }
+
+    /// Executes the instructions of one 60th of a second (IPS / 60 on average) then ticks the timers.
+    /// The timers only depend on the number of executed instructions, not on the time it took,
+    /// so the same rom with the same inputs always gives the same timer values.
+    /// With the display wait quirk the frame ends after the first sprite drawn.
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        self.run_frame_until(|_| false).map(|_| ())
+    }
+
+    /// Same as `run_frame` but `stop` is called before each instruction, if it returns true
+    /// the frame is paused there and `true` is returned. The next call finishes the frame.
+    pub fn run_frame_until(
+        &mut self,
+        mut stop: impl FnMut(&Chip8) -> bool,
+    ) -> Result<bool, EmulatorError> {
+        if !self.in_frame {
+            self.frames += 1;
+            self.in_frame = true;
+        }
+        let budget = self.frames * self.options.ips / TIMER_FREQUENCY;
+        while self.cycles + self.waited < budget && !self.exited {
+            if stop(self) {
+                return Ok(true);
+            }
+            let draws = self.options.quirks.display_wait
+                && matches!(
+                    Opcode::decode(self.memory.peek_word(self.pc), self.options.platform),
+                    Some(Opcode::Draw(..))
+                );
+            self.step()?;
+            if draws {
+                // The COSMAC VIP waited for the vertical blank interrupt before drawing
+                self.waited = budget.max(self.cycles + self.waited) - self.cycles;
+                break;
+            }
+        }
+        self.in_frame = false;
+        self.tick_timers();
+        Ok(false)
+    }
+
+    /// Decrements the delay and sound timers, should be called 60 times per second.
+    /// `run_frame` already does it.
+    pub fn tick_timers(&mut self) {
+        self.memory.decrement_delay_timer();
+        self.memory.decrement_sound_timer();
+    }
+
+    /// The beeper is on as long as the sound timer is not 0.
+    pub fn is_beeping(&self) -> bool {
+        self.memory.read_sound_timer() > 0
+    }
+
+    /// The 128 1-bit samples played in loop by the beeper, and how fast (see `playback_rate`).
+    pub fn audio_pattern(&self) -> ([u8; 16], f32) {
+        let pitch = self.memory.read_pitch();
+        (self.memory.read_audio_pattern(), playback_rate(pitch))
+    }
+
+    /// The rom asked to exit the interpreter (SUPER-CHIP 00FD).
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // KEYS
+
+    pub fn press_key(&mut self, key: u8) {
+        self.keys_state.update_state(key, true);
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.keys_state.update_state(key, false);
+    }
+
+    // WATCHPOINTS
+
+    /// Watches the accesses of the instructions to some adresses, see `watch_hits`.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.memory.add_watchpoint(watchpoint);
+    }
+
+    /// Removes the watchpoints starting at `start`, returns false if there were none.
+    pub fn remove_watchpoints(&mut self, start: u16) -> bool {
+        self.memory.remove_watchpoints(start)
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        self.memory.watchpoints()
+    }
+
+    /// The accesses of the last executed instruction to watched adresses.
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    /// The last instruction accessed an adress watched by a watchpoint that is not in log mode.
+    pub fn watch_triggered(&self) -> bool {
+        self.watch_hits.iter().any(|hit| !hit.log)
+    }
+
+    /// The accesses to the adresses watched in log mode since the last call.
+    pub fn take_watch_log(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_log)
+    }
+
+    // TRACE
+
+    /// Records the instructions executed from now on, `None` to stop.
+    pub fn set_trace(&mut self, trace: Option<Trace>) {
+        self.trace = trace;
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    // SAVE STATES
+
+    /// Copies the whole machine, with the quirks it runs with. See `Snapshot::to_bytes` to save it.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            stack: self.stack.clone(),
+            memory: self.memory.clone(),
+            screen: self.screen.clone(),
+            platform: self.options.platform,
+            ips: self.options.ips,
+            quirks: self.options.quirks,
+            cycles: self.cycles,
+            waited: self.waited,
+            frames: self.frames,
+            in_frame: self.in_frame,
+            exited: self.exited,
+        }
+    }
+
+    /// Puts the machine back in the state of `snapshot`, the held keys don't change.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.pc = snapshot.pc;
+        self.stack = snapshot.stack;
+        let mut memory = snapshot.memory;
+        self.memory.move_watchpoints(&mut memory);
+        self.memory = memory;
+        self.watch_hits.clear();
+        self.screen = snapshot.screen;
+        self.options.platform = snapshot.platform;
+        self.options.ips = snapshot.ips;
+        self.options.quirks = snapshot.quirks;
+        self.cycles = snapshot.cycles;
+        self.waited = snapshot.waited;
+        self.frames = snapshot.frames;
+        self.in_frame = snapshot.in_frame;
+        self.exited = snapshot.exited;
+        // A FX0A of the state starts waiting again
+        self.keys_state.set_wait(KeyWait::Idle);
+    }
+
+    // STATE
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn options(&self) -> &LaunchOptions {
+        &self.options
+    }
+
+    /// The framebuffer, 64x32 pixels or 128x64 in SUPER-CHIP hires mode.
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// Returns the framebuffer if it changed since the last call.
+    pub fn take_frame(&mut self) -> Option<&Screen> {
+        if self.screen.is_dirty() {
+            self.screen.mark_clean();
+            Some(&self.screen)
+        } else {
+            None
+        }
+    }
+}
+
+/// Samples of the audio pattern played per second for an XO-CHIP pitch, 4000 Hz for the default one.
+pub fn playback_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+}
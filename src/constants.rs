@@ -1,2 +1,9 @@
 pub const NB_REGISTERS: usize = 16;
+pub const MAX_STACK_DEPTH: usize = 16; // nested subroutine calls
+pub const MEMORY_SIZE: usize = 0x1000; // 4 KiB
+pub const XO_MEMORY_SIZE: usize = 0x10000; // 64 KiB on XO-CHIP
+pub const DEFAULT_PITCH: u8 = 64; // XO-CHIP pitch for a 4000 Hz playback rate of the audio pattern
 pub const FONT_ADRESS: u16 = 0x50;
+pub const BIG_FONT_ADRESS: u16 = 0xA0; // right after the 80 bytes of the small font
+pub const TIMER_FREQUENCY: u64 = 60; // the delay and sound timers are decremented 60 times per second
+pub const DEFAULT_AUDIO_PATTERN: [u8; 16] = [0xF0; 16]; // square wave of 500 Hz at the default pitch
@@ -1,20 +1,68 @@
 use std::fmt;
 
+/// Why the emulation stopped, `pc` is the adress of the instruction that failed.
+#[derive(Clone, PartialEq, Debug)]
+pub enum EmulatorError {
+    BadRom(String),
+    RomTooLarge { size: usize, max: usize },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    OutOfBounds { pc: u16, adress: usize },
+    UnknownOpcode { pc: u16, instruction: u16 },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::BadRom(reason) => write!(f, "Bad rom: {reason}"),
+            EmulatorError::RomTooLarge { size, max } => {
+                write!(
+                    f,
+                    "The rom is too large: {size} bytes, the memory holds {max} from 0x200"
+                )
+            }
+            EmulatorError::StackOverflow { pc } => {
+                write!(
+                    f,
+                    "Stack overflow: 0x{pc:03X} calls too many nested subroutines"
+                )
+            }
+            EmulatorError::StackUnderflow { pc } => {
+                write!(
+                    f,
+                    "Stack underflow: 0x{pc:03X} returns outside of a subroutine"
+                )
+            }
+            EmulatorError::OutOfBounds { pc, adress } => {
+                write!(
+                    f,
+                    "Out of bounds memory access: 0x{pc:03X} accesses 0x{adress:04X}"
+                )
+            }
+            EmulatorError::UnknownOpcode { pc, instruction } => {
+                write!(f, "Unknown instruction: 0x{pc:03X} | 0x{instruction:04X}")
+            }
+        }
+    }
+}
+
+pub struct QuitGameError;
+
+/// An error of the assembler, at a column (from 1) of a line (from 1) of a source file.
 #[derive(Debug)]
-pub struct NonUsedInstructionError {
-    pub pc: u16,
-    pub instruction: u16,
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
 }
 
-impl fmt::Display for NonUsedInstructionError {
+impl fmt::Display for AsmError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Non used instruction: 0x{:03X} | 0x{:04X}",
-            self.pc - 2,
-            self.instruction
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
         )
     }
 }
-
-pub struct QuitGameError;
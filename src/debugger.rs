@@ -0,0 +1,427 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::chip8::Chip8;
+use crate::constants::*;
+use crate::custom_errors::EmulatorError;
+use crate::memory::{Access, Watchpoint};
+
+const TRACE_LENGTH: usize = 20; // instructions shown by the trace command
+
+pub const HELP: &str = "Debugger commands :
+  c, continue        Resume the execution
+  p, pause           Pause the execution
+  s, step            Execute one instruction
+  n, next            Execute one instruction, a whole subroutine for 2NNN
+  b, break <ADR>     Add a breakpoint at the adress ADR (hexadecimal), list them without ADR
+  d, delete <ADR>    Remove the breakpoint at the adress ADR
+  w, watch <ADR>[-<END>] [rwx] [log]
+                     Pause after an instruction reads (r), writes (w, the default) or executes (x)
+                     the adresses ADR to END, only print the accesses with log. List them without ADR
+  u, unwatch <ADR>   Remove the watchpoints starting at the adress ADR
+  r, regs            Show the registers, the stack and the timers
+  k, stack           Show the subroutines being executed, the last called first
+  t, trace [N]       Show the last N instructions executed [default: 20]
+  h, help            Show this help";
+
+/// Pauses a `Chip8` on breakpoints and executes it step by step, driven by text commands.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    paused: bool,
+    step_over: Option<(u16, usize)>, // pause when pc is back at this adress with this stack size
+    resuming: bool,                  // don't stop on the breakpoint we are resuming from
+}
+
+impl Debugger {
+    pub fn new(paused: bool) -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            paused,
+            step_over: None,
+            resuming: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.step_over = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.resuming = true;
+    }
+
+    pub fn add_breakpoint(&mut self, adress: u16) {
+        self.breakpoints.insert(adress);
+    }
+
+    pub fn remove_breakpoint(&mut self, adress: u16) -> bool {
+        self.breakpoints.remove(&adress)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    // EXECUTION
+
+    /// Runs one frame of `chip8` unless paused. Returns true if it just stopped on a breakpoint,
+    /// after a watchpoint was triggered or at the end of a step over. The errors (stack overflow...) pause on the instruction that failed.
+    pub fn run_frame(&mut self, chip8: &mut Chip8) -> Result<bool, EmulatorError> {
+        if self.paused {
+            return Ok(false);
+        }
+        let breakpoints = &self.breakpoints;
+        let step_over = self.step_over;
+        let resuming = &mut self.resuming;
+        let stopped = chip8.run_frame_until(|chip8| {
+            let stop = !*resuming
+                && (breakpoints.contains(&chip8.pc())
+                    || chip8.watch_triggered()
+                    || step_over == Some((chip8.pc(), chip8.stack().len())));
+            *resuming = false;
+            stop
+        });
+        let stopped = stopped.inspect_err(|_| self.pause())?;
+        if stopped {
+            self.pause();
+        }
+        Ok(stopped)
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<(), EmulatorError> {
+        self.pause();
+        chip8.step()
+    }
+
+    /// Executes one instruction, or runs until the subroutine returns if it is a call (2NNN).
+    /// Returns true if the execution is still running to the end of the subroutine.
+    pub fn step_over(&mut self, chip8: &mut Chip8) -> Result<bool, EmulatorError> {
+        let pc = chip8.pc();
+        if chip8.memory().peek_word(pc) & 0xF000 != 0x2000 {
+            self.step(chip8)?;
+            return Ok(false);
+        }
+        self.step_over = Some((pc.wrapping_add(2), chip8.stack().len()));
+        self.resume();
+        Ok(true)
+    }
+
+    // COMMANDS
+
+    /// Executes a debugger command (see `HELP`) and returns what to show the user.
+    pub fn execute(&mut self, chip8: &mut Chip8, command: &str) -> Result<String, EmulatorError> {
+        let mut words = command.split_whitespace();
+        let name = words.next();
+        if let Some("w" | "watch") = name {
+            return Ok(watch(chip8, &words.collect::<Vec<_>>()));
+        }
+        if let Some("t" | "trace") = name {
+            return Ok(trace(chip8, words.next()));
+        }
+        let adress = words.next().map(|adress| {
+            u16::from_str_radix(adress.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid adress: {adress}"))
+        });
+
+        let output = match (name, adress) {
+            (Some("c" | "continue"), _) => {
+                self.resume();
+                "Running".to_string()
+            }
+            (Some("p" | "pause"), _) => {
+                self.pause();
+                state(chip8)
+            }
+            (Some("s" | "step"), _) => {
+                self.step(chip8)?;
+                state(chip8)
+            }
+            (Some("n" | "next"), _) => {
+                if self.step_over(chip8)? {
+                    "Running until the subroutine returns".to_string()
+                } else {
+                    state(chip8)
+                }
+            }
+            (Some("b" | "break"), None) => {
+                let list: Vec<String> = self.breakpoints().map(|b| format!("0x{b:03X}")).collect();
+                format!("Breakpoints: {}", list.join(" "))
+            }
+            (Some("b" | "break"), Some(Ok(adress))) => {
+                self.add_breakpoint(adress);
+                format!("Breakpoint added at 0x{adress:03X}")
+            }
+            (Some("d" | "delete"), Some(Ok(adress))) => {
+                if self.remove_breakpoint(adress) {
+                    format!("Breakpoint removed at 0x{adress:03X}")
+                } else {
+                    format!("No breakpoint at 0x{adress:03X}")
+                }
+            }
+            (Some("u" | "unwatch"), Some(Ok(adress))) => {
+                if chip8.remove_watchpoints(adress) {
+                    format!("Watchpoints removed at 0x{adress:03X}")
+                } else {
+                    format!("No watchpoint at 0x{adress:03X}")
+                }
+            }
+            (Some("d" | "delete" | "u" | "unwatch"), None) => "Missing adress".to_string(),
+            (Some("b" | "break" | "d" | "delete" | "u" | "unwatch"), Some(Err(message))) => message,
+            (Some("r" | "regs"), _) => state(chip8),
+            (Some("k" | "stack"), _) => backtrace(chip8),
+            (Some("h" | "help"), _) => HELP.to_string(),
+            (None, _) => String::new(),
+            (Some(command), _) => format!("Unknown command: {command}, try help"),
+        };
+        Ok(output)
+    }
+}
+
+/// PC, next instruction, I, timers, V0 to VF and the stack of `chip8`, on a few lines.
+pub fn state(chip8: &Chip8) -> String {
+    let memory = chip8.memory();
+    // The pc can be past the end of the memory after an error
+    let instruction = match chip8.pc() as usize + 1 < memory.size() {
+        true => format!("0x{:04X}", memory.peek_word(chip8.pc())),
+        false => "------".to_string(),
+    };
+    let mut state = format!(
+        "PC 0x{:03X} ({instruction})   I 0x{:03X}   DT {:3}   ST {:3}\n",
+        chip8.pc(),
+        memory.read_adress(),
+        memory.read_delay_timer(),
+        memory.read_sound_timer(),
+    );
+    for i in 0..NB_REGISTERS {
+        let _ = write!(state, "V{:X} {:02X}  ", i, memory.read_register(i));
+        if i == NB_REGISTERS / 2 - 1 {
+            state.push('\n');
+        }
+    }
+    let stack = chip8.stack();
+    let _ = write!(state, "\nStack {}/{}:", stack.len(), stack.depth());
+    for adress in stack.adresses() {
+        let _ = write!(state, " 0x{adress:03X}");
+    }
+    for hit in chip8.watch_hits() {
+        let _ = write!(state, "\nWatchpoint: {hit}");
+    }
+    state
+}
+
+// The trace is kept by main while the debugger runs, unless --trace-buffer is 0
+fn trace(chip8: &Chip8, count: Option<&str>) -> String {
+    let count = match count {
+        None => TRACE_LENGTH,
+        Some(count) => match count.parse() {
+            Ok(count) => count,
+            Err(_) => return format!("Invalid number of instructions: {count}"),
+        },
+    };
+    match chip8.trace() {
+        Some(trace) if !trace.entries().is_empty() => trace.last_to_text(count),
+        _ => "No instruction traced".to_string(),
+    }
+}
+
+// The arguments of the watch command : lists the watchpoints or adds one
+fn watch(chip8: &mut Chip8, args: &[&str]) -> String {
+    let Some(range) = args.first() else {
+        let list: Vec<String> = chip8.watchpoints().iter().map(describe).collect();
+        return format!("Watchpoints: {}", list.join(", "));
+    };
+    let parse = |adress: &str| {
+        u16::from_str_radix(adress.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Invalid adress: {adress}"))
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse(start), parse(end)),
+        None => (parse(range), parse(range)),
+    };
+    let (start, end) = match (start, end) {
+        (Ok(start), Ok(end)) if start <= end => (start, end),
+        (Ok(_), Ok(_)) => return format!("Invalid range: {range}"),
+        (Err(message), _) | (_, Err(message)) => return message,
+    };
+
+    let mut watchpoint = Watchpoint {
+        start,
+        end,
+        accesses: Vec::new(),
+        log: false,
+    };
+    for arg in &args[1..] {
+        if *arg == "log" {
+            watchpoint.log = true;
+            continue;
+        }
+        for access in arg.chars() {
+            watchpoint.accesses.push(match access {
+                'r' => Access::Read,
+                'w' => Access::Write,
+                'x' => Access::Execute,
+                _ => return format!("Invalid access: {access} (expected r, w or x)"),
+            });
+        }
+    }
+    if watchpoint.accesses.is_empty() {
+        watchpoint.accesses.push(Access::Write);
+    }
+    let description = describe(&watchpoint);
+    chip8.add_watchpoint(watchpoint);
+    format!("Watchpoint added: {description}")
+}
+
+// 0x3F0-0x3F2 rw log
+fn describe(watchpoint: &Watchpoint) -> String {
+    let mut description = format!("0x{:03X}", watchpoint.start);
+    if watchpoint.end != watchpoint.start {
+        let _ = write!(description, "-0x{:03X}", watchpoint.end);
+    }
+    description.push(' ');
+    for (access, letter) in [
+        (Access::Read, 'r'),
+        (Access::Write, 'w'),
+        (Access::Execute, 'x'),
+    ] {
+        if watchpoint.accesses.contains(&access) {
+            description.push(letter);
+        }
+    }
+    if watchpoint.log {
+        description.push_str(" log");
+    }
+    description
+}
+
+// The 2NNN of each subroutine being executed and where it returns
+fn backtrace(chip8: &Chip8) -> String {
+    let stack = chip8.stack();
+    if stack.is_empty() {
+        return "Not in a subroutine".to_string();
+    }
+    let mut backtrace = format!("Stack {}/{}:", stack.len(), stack.depth());
+    for (level, adress) in stack.adresses().iter().enumerate().rev() {
+        let call = adress.wrapping_sub(2);
+        let _ = write!(
+            backtrace,
+            "\n  {level:2}  0x{call:03X} calls 0x{:03X}, returns to 0x{adress:03X}",
+            chip8.memory().peek_word(call) & 0x0FFF
+        );
+    }
+    backtrace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch_options::{LaunchOptions, Platform};
+
+    // V0 = 1, call 0x208, V0 += 1, jump to itself. The subroutine at 0x208 sets V1 to 2
+    const ROM: [u8; 12] = [
+        0x60, 0x01, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06, 0x61, 0x02, 0x00, 0xEE,
+    ];
+
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::new(LaunchOptions::default());
+        chip8.load_rom(&ROM).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn the_breakpoints_pause_then_the_execution_resumes_past_them() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new(false);
+        debugger.add_breakpoint(0x204);
+        assert!(debugger.run_frame(&mut chip8).unwrap());
+        assert!(debugger.is_paused());
+        assert_eq!((chip8.pc(), chip8.memory().read_register(0)), (0x204, 1));
+
+        // The breakpoint we are resuming from doesn't stop the execution again
+        debugger.resume();
+        assert!(!debugger.run_frame(&mut chip8).unwrap());
+        assert!(!debugger.is_paused());
+        assert_eq!((chip8.pc(), chip8.memory().read_register(0)), (0x206, 2));
+    }
+
+    #[test]
+    fn the_breakpoint_commands_add_list_and_remove() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new(true);
+        let mut run = |command| debugger.execute(&mut chip8, command).unwrap();
+        assert_eq!(run("b 204"), "Breakpoint added at 0x204");
+        assert_eq!(run("break 0x20A"), "Breakpoint added at 0x20A");
+        assert_eq!(run("b"), "Breakpoints: 0x204 0x20A");
+        assert_eq!(run("d 204"), "Breakpoint removed at 0x204");
+        assert_eq!(run("d 204"), "No breakpoint at 0x204");
+        assert_eq!(run("b 2G0"), "Invalid adress: 2G0");
+        assert_eq!(run("d"), "Missing adress");
+    }
+
+    #[test]
+    fn next_runs_the_whole_subroutine() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new(true);
+        debugger.execute(&mut chip8, "s").unwrap();
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(
+            debugger.execute(&mut chip8, "n").unwrap(),
+            "Running until the subroutine returns"
+        );
+        // Pauses on the instruction after the call, once back at the same stack size
+        assert!(debugger.run_frame(&mut chip8).unwrap());
+        assert_eq!((chip8.pc(), chip8.stack().len()), (0x204, 0));
+        assert_eq!(chip8.memory().read_register(1), 2);
+
+        // Any other instruction is a single step
+        debugger.execute(&mut chip8, "next").unwrap();
+        assert!(debugger.is_paused());
+        assert_eq!(chip8.pc(), 0x206);
+    }
+
+    #[test]
+    fn next_on_a_call_at_the_end_of_the_memory() {
+        let mut chip8 = Chip8::new(LaunchOptions {
+            platform: Platform::XoChip,
+            ..LaunchOptions::default()
+        });
+        // A call to 0x200 at 0xFFFE, the last adress of the 64 KiB memory
+        let mut rom = vec![0; 0xFE00];
+        rom[0xFDFE..].copy_from_slice(&[0x22, 0x00]);
+        chip8.load_rom(&rom).unwrap();
+        let mut snapshot = chip8.snapshot();
+        snapshot.pc = 0xFFFE;
+        chip8.restore(snapshot);
+        let mut debugger = Debugger::new(true);
+        assert_eq!(
+            debugger.execute(&mut chip8, "n").unwrap(),
+            "Running until the subroutine returns"
+        );
+    }
+
+    #[test]
+    fn the_watch_command_parses_its_arguments() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new(true);
+        let mut run = |command| debugger.execute(&mut chip8, command).unwrap();
+        assert_eq!(run("w 0x300-0x2FF"), "Invalid range: 0x300-0x2FF");
+        assert_eq!(run("w 300-3G0"), "Invalid adress: 3G0");
+        assert_eq!(run("w 300 q"), "Invalid access: q (expected r, w or x)");
+        assert_eq!(
+            run("w 300-302 rwx log"),
+            "Watchpoint added: 0x300-0x302 rwx log"
+        );
+        assert_eq!(run("watch 304"), "Watchpoint added: 0x304 w");
+        assert_eq!(run("w"), "Watchpoints: 0x300-0x302 rwx log, 0x304 w");
+        assert_eq!(run("u 300"), "Watchpoints removed at 0x300");
+        assert_eq!(run("u 300"), "No watchpoint at 0x300");
+        assert_eq!(run("w"), "Watchpoints: 0x304 w");
+    }
+}
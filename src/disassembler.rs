@@ -0,0 +1,202 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::Write;
+
+use crate::launch_options::Platform;
+use crate::opcodes::Opcode;
+
+const START: u16 = 0x200;
+const BYTES_PER_DATA_LINE: usize = 8;
+
+/// Disassembles a rom loaded at 0x200 into mnemonics, one instruction per line.
+/// The code is found by following the execution from 0x200 through jumps, calls and skips,
+/// the bytes that are never reached are shown as data (`DB`).
+/// The jump and call targets get a label `L2A4:` and the output can be read back by `chip asm`.
+pub fn disassemble(rom: &[u8], platform: Platform) -> String {
+    let end = START as usize + rom.len();
+    let word = |adress: u16| -> Option<u16> {
+        let i = (adress - START) as usize;
+        Some(u16::from_be_bytes([*rom.get(i)?, *rom.get(i + 1)?]))
+    };
+    let in_rom = |adress: u16| (START as usize..end).contains(&(adress as usize));
+
+    // Find the code
+    let mut code = vec![false; rom.len()]; // first byte of an instruction
+    let mut labels = BTreeSet::new();
+    let mut todo = VecDeque::from([START]);
+    while let Some(adress) = todo.pop_front() {
+        if !in_rom(adress) || code[(adress - START) as usize] {
+            continue;
+        }
+        let Some(op) = word(adress).and_then(|w| Opcode::decode(w, platform)) else {
+            continue;
+        };
+        if op == Opcode::SetILong && adress.checked_add(2).and_then(word).is_none() {
+            continue;
+        }
+        code[(adress - START) as usize] = true;
+        // The walk stops at the end of the 64 KiB of XO-CHIP
+        let next = adress.checked_add(op.size());
+
+        match op {
+            Opcode::Jump(target) => {
+                labels.insert(target);
+                todo.push_back(target);
+            }
+            Opcode::Call(target) => {
+                labels.insert(target);
+                todo.push_back(target);
+                todo.extend(next);
+            }
+            Opcode::SetI(target) => {
+                labels.insert(target);
+                todo.extend(next);
+            }
+            // The destination of BNNN depends on a register
+            Opcode::JumpOffset(..) | Opcode::Ret | Opcode::Exit => {}
+            // Unknown machine code routines, the VIP hires clear 0230 is the only one we run
+            Opcode::Sys(NNN) if NNN != 0x230 => {}
+            _ if op.is_skip() => {
+                // The skipped instruction may be F000 NNNN on XO-CHIP
+                let skipped = next
+                    .and_then(word)
+                    .and_then(|w| Opcode::decode(w, platform));
+                todo.extend(next);
+                todo.extend(
+                    next.and_then(|next| next.checked_add(skipped.map_or(2, |op| op.size()))),
+                );
+            }
+            _ => todo.extend(next),
+        }
+    }
+    labels.retain(|adress| in_rom(*adress));
+
+    // Write it
+    let label = |adress: u16| -> String {
+        if labels.contains(&adress) {
+            format!("L{adress:03X}")
+        } else {
+            format!("0x{adress:03X}")
+        }
+    };
+    let mut output = String::new();
+    let mut adress = START;
+    while in_rom(adress) {
+        let i = (adress - START) as usize;
+        if labels.contains(&adress) {
+            let _ = writeln!(output, "L{adress:03X}:");
+        }
+
+        // An instruction overlapping a label is shown as data so that the label is kept
+        let size = word(adress)
+            .and_then(|w| Opcode::decode(w, platform))
+            .map_or(2, |op| op.size());
+        let overlaps = (1..size)
+            .filter_map(|offset| adress.checked_add(offset))
+            .any(|a| labels.contains(&a));
+
+        if code[i] && !overlaps {
+            let op = Opcode::decode(word(adress).unwrap_or(0), platform).unwrap_or(Opcode::Cls);
+            let (text, bytes) = match op {
+                Opcode::Jump(NNN) => (format!("JP {}", label(NNN)), 2),
+                Opcode::Call(NNN) => (format!("CALL {}", label(NNN)), 2),
+                Opcode::SetI(NNN) => (format!("LD I, {}", label(NNN)), 2),
+                Opcode::SetILong => {
+                    let NNNN = adress.checked_add(2).and_then(word).unwrap_or(0);
+                    (format!("LD I, LONG 0x{NNNN:04X}"), 4)
+                }
+                op => (op.to_string(), 2),
+            };
+            let hex: String = rom[i..i + bytes]
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect();
+            let _ = writeln!(output, "    {text:<20} ; 0x{adress:03X}  {hex}");
+            let Some(next) = adress.checked_add(bytes as u16) else {
+                break;
+            };
+            adress = next;
+        } else {
+            // Data until the next instruction or label
+            let start = adress;
+            let mut data = Vec::new();
+            let mut last = false; // the data reaches 0xFFFF
+            while in_rom(adress)
+                && (data.is_empty() || !code[(adress - START) as usize])
+                && data.len() < BYTES_PER_DATA_LINE
+                && (data.is_empty() || !labels.contains(&adress))
+            {
+                data.push(format!("0x{:02X}", rom[(adress - START) as usize]));
+                match adress.checked_add(1) {
+                    Some(next) => adress = next,
+                    None => {
+                        last = true;
+                        break;
+                    }
+                }
+            }
+            let _ = writeln!(
+                output,
+                "    {:<20} ; 0x{start:03X}",
+                format!("DB {}", data.join(", "))
+            );
+            if last {
+                break;
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 27] = [
+        0x00, 0xFF, 0x00, 0xC4, 0xF0, 0x00, 0x12, 0x34, 0x51, 0x22, 0xF2, 0x01, 0x30, 0x00, 0xF0,
+        0x00, 0xAB, 0xCD, 0xF1, 0x30, 0xF2, 0x75, 0x12, 0x16, 0xAA, 0xBB, 0xCC,
+    ];
+
+    #[test]
+    fn the_xo_chip_instructions_are_disassembled() {
+        assert_eq!(
+            disassemble(&ROM, Platform::XoChip),
+            "    HIGH                 ; 0x200  00FF
+    SCD 4                ; 0x202  00C4
+    LD I, LONG 0x1234    ; 0x204  F0001234
+    SAVE V1, V2          ; 0x208  5122
+    PLANE 2              ; 0x20A  F201
+    SE V0, 0x00          ; 0x20C  3000
+    LD I, LONG 0xABCD    ; 0x20E  F000ABCD
+    LD HF, V1            ; 0x212  F130
+    LD R, V2             ; 0x214  F275
+L216:
+    JP L216              ; 0x216  1216
+    DB 0xAA, 0xBB, 0xCC  ; 0x218
+"
+        );
+    }
+
+    #[test]
+    fn the_instructions_of_other_platforms_are_data() {
+        assert_eq!(
+            disassemble(&ROM[..8], Platform::Chip8),
+            "    SYS 0x0FF            ; 0x200  00FF
+    DB 0x00, 0xC4, 0xF0, 0x00, 0x12, 0x34 ; 0x202
+"
+        );
+    }
+
+    #[test]
+    fn the_disassembly_stops_at_the_end_of_the_memory() {
+        let mut rom = [0x60, 0x00].repeat((0x10000 - START as usize) / 2);
+        let last = |rom: &[u8]| {
+            let output = disassemble(rom, Platform::XoChip);
+            output.lines().last().unwrap().to_string()
+        };
+        assert_eq!(last(&rom), "    LD V0, 0x00          ; 0xFFFE  6000");
+        // F000 without its adress
+        let len = rom.len();
+        rom[len - 2] = 0xF0;
+        assert_eq!(last(&rom), "    DB 0xF0, 0x00        ; 0xFFFE");
+    }
+}
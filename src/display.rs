@@ -1,6 +1,5 @@
-use crate::launch_options::*;
-use crate::screen;
-use crate::screen::Screen;
+use chip::launch_options::LaunchOptions;
+use chip::screen;
 
 use sdl2::{
     pixels::Color,
@@ -12,47 +11,69 @@ use sdl2::{
 
 const SCREEN_WIDTH: u32 = 1280;
 const SCREEN_HEIGHT: u32 = 640;
-const SIZE_PIXEL: u32 = 20;
 
-pub fn init() -> Result<(Sdl, Canvas<Window>), String> {
+pub fn init(options: &LaunchOptions) -> Result<(Sdl, Canvas<Window>), String> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     let window = video_subsystem
-        .window("CHIP-8", SCREEN_WIDTH, SCREEN_HEIGHT)
+        .window(
+            options.title.as_deref().unwrap_or("CHIP-8"),
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+        )
         .position_centered()
         .build()
         .unwrap();
 
     let mut canvas = window
         .into_canvas()
+        .present_vsync()
         .build()
         .expect("Could not make a canvas");
 
-    clear_screen(&mut canvas);
+    clear_screen(&mut canvas, options);
     Ok((sdl_context, canvas))
 }
 
-pub fn display(canvas: &mut WindowCanvas, screen: &screen::Screen) -> Result<(), String> {
-    for (x, y) in Screen::iter_coords() {
-        if screen.is_on(x, y) {
-            canvas.set_draw_color(Color::RGB(PIXEL_ON.0, PIXEL_ON.1, PIXEL_ON.2));
-        } else {
-            canvas.set_draw_color(Color::RGB(PIXEL_OFF.0, PIXEL_OFF.1, PIXEL_OFF.2));
+pub fn display(
+    canvas: &mut WindowCanvas,
+    screen: &screen::Screen,
+    options: &LaunchOptions,
+) -> Result<(), String> {
+    // Color of the pixels for each combination of planes, only plane 1 is used outside of XO-CHIP
+    let off = options.pixel_off;
+    let colors = [options.pixel_on, options.pixel_plane_2, options.pixel_both];
+    // 20x20 in lores (64x32), 10x10 in hires (128x64), 20x10 in the 64x64 hires of the VIP
+    let width_pixel = SCREEN_WIDTH / screen.width() as u32;
+    let height_pixel = SCREEN_HEIGHT / screen.height() as u32;
+
+    // Only the pixels that are on are drawn, all at once per color, on top of the cleared canvas
+    let mut pixels_on: [Vec<Rect>; 3] = Default::default();
+    for (x, y) in screen.iter_coords() {
+        let planes = screen.pixel(x, y);
+        if planes != 0 {
+            pixels_on[planes as usize - 1].push(Rect::new(
+                x as i32 * width_pixel as i32,
+                y as i32 * height_pixel as i32,
+                width_pixel,
+                height_pixel,
+            ));
         }
-        let pixel_emplacement = Rect::new(
-            x as i32 * SIZE_PIXEL as i32,
-            y as i32 * SIZE_PIXEL as i32,
-            SIZE_PIXEL,
-            SIZE_PIXEL,
-        );
-        canvas.fill_rect(pixel_emplacement)?;
+    }
+
+    canvas.set_draw_color(Color::RGB(off.0, off.1, off.2));
+    canvas.clear();
+    for (color, pixels) in colors.iter().zip(pixels_on.iter()) {
+        canvas.set_draw_color(Color::RGB(color.0, color.1, color.2));
+        canvas.fill_rects(pixels)?;
     }
     canvas.present();
     Ok(())
 }
 
-pub fn clear_screen(canvas: &mut WindowCanvas) {
-    canvas.set_draw_color(Color::RGB(PIXEL_OFF.0, PIXEL_OFF.1, PIXEL_OFF.2));
+pub fn clear_screen(canvas: &mut WindowCanvas, options: &LaunchOptions) {
+    let off = options.pixel_off;
+    canvas.set_draw_color(Color::RGB(off.0, off.1, off.2));
     canvas.clear();
     canvas.present();
 }
@@ -1,36 +1,189 @@
-use crate::custom_errors::*;
+use chip::chip8::Chip8;
+use chip::custom_errors::*;
+use chip::keymap::KeyMap;
+
+use std::collections::HashSet;
+
+use sdl2::{
+    controller::{Axis, GameController},
+    event::Event,
+    keyboard::Keycode,
+    GameControllerSubsystem, Sdl,
+};
+
+const STICK_DEAD_ZONE: i16 = 16000; // of 32767, the left stick is used like the directional pad
+
+// Keys of the emulator itself, the CHIP-8 keys are sent to the machine directly
+pub enum Hotkey {
+    SaveState,    // F5
+    LoadState,    // F9
+    PreviousSlot, // F6
+    NextSlot,     // F7
+    Rewind(bool), // Backspace, true while it is held
+}
+
+/// The controllers plugged in, by order of connection : the first one is the player 1.
+/// They can be plugged and unplugged while playing.
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    plugged: Vec<Controller>,
+    held: HashSet<Source>, // the keys, buttons and stick directions held down
+}
 
-use sdl2::{event::Event, keyboard::Keycode, Sdl};
+// Something that holds a CHIP-8 key down. Several of them can be mapped to the same key,
+// which stays pressed until all of them are released
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Source {
+    Key(String),              // keyboard key name
+    Button(u32, String),      // controller instance id, button name
+    Stick(u32, &'static str), // controller instance id, direction as a button name
+}
 
-pub struct KeysState {
-    keys: [bool; 16],
+struct Controller {
+    controller: GameController,
+    stick: [Option<&'static str>; 2], // direction of the left stick on each axis, as a button
 }
 
-impl KeysState {
-    pub fn new() -> Self {
-        KeysState { keys: [false; 16] }
+impl Controllers {
+    pub fn new(sdl_context: &Sdl) -> Result<Controllers, String> {
+        // SDL sends a ControllerDeviceAdded event for the controllers already plugged in
+        Ok(Controllers {
+            subsystem: sdl_context.game_controller()?,
+            plugged: Vec::new(),
+            held: HashSet::new(),
+        })
+    }
+
+    // The CHIP-8 key of a source, the buttons depend on the player of the controller
+    fn key(&self, source: &Source, keymap: &KeyMap) -> Option<u8> {
+        match source {
+            Source::Key(name) => keymap.key(name),
+            Source::Button(which, button) => keymap.button(self.player(*which)?, button),
+            Source::Stick(which, button) => keymap.button(self.player(*which)?, button),
+        }
+    }
+
+    fn keys_held(&self, keymap: &KeyMap) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for key in self
+            .held
+            .iter()
+            .filter_map(|source| self.key(source, keymap))
+        {
+            keys[key as usize] = true;
+        }
+        keys
+    }
+
+    // Presses and releases the CHIP-8 keys whose state changed with `change`
+    fn update_keys(&mut self, chip8: &mut Chip8, keymap: &KeyMap, change: impl FnOnce(&mut Self)) {
+        let before = self.keys_held(keymap);
+        change(self);
+        let after = self.keys_held(keymap);
+        for (key, (before, after)) in before.into_iter().zip(after).enumerate() {
+            match (before, after) {
+                (false, true) => chip8.press_key(key as u8),
+                (true, false) => chip8.release_key(key as u8),
+                _ => {}
+            }
+        }
+    }
+
+    fn hold(&mut self, source: Source, held: bool, chip8: &mut Chip8, keymap: &KeyMap) {
+        self.update_keys(chip8, keymap, |controllers| {
+            if held {
+                controllers.held.insert(source);
+            } else {
+                controllers.held.remove(&source);
+            }
+        });
     }
 
-    pub fn read_state(&self, key: u8) -> bool {
-        self.keys[key as usize]
+    fn player(&self, which: u32) -> Option<usize> {
+        self.plugged
+            .iter()
+            .position(|plugged| plugged.controller.instance_id() == which)
     }
 
-    pub fn is_key_pressed(&self) -> Option<u8> {
-        for (i, key) in self.keys.iter().enumerate() {
-            if *key {
-                return Some(i as u8);
+    fn plug(&mut self, joystick_index: u32) {
+        match self.subsystem.open(joystick_index) {
+            Ok(controller) => {
+                println!(
+                    "Controller {} plugged in (player {})",
+                    controller.name(),
+                    self.plugged.len() + 1
+                );
+                self.plugged.push(Controller {
+                    controller,
+                    stick: [None; 2],
+                });
             }
+            Err(err) => eprintln!("Could not open the controller {joystick_index} : {err}"),
+        }
+    }
+
+    // The next players take the place of the unplugged one, their buttons held now press
+    // the keys of their new player
+    fn unplug(&mut self, which: u32, chip8: &mut Chip8, keymap: &KeyMap) {
+        if let Some(player) = self.player(which) {
+            self.update_keys(chip8, keymap, |controllers| {
+                controllers.held.retain(|source| match source {
+                    Source::Key(_) => true,
+                    Source::Button(id, _) | Source::Stick(id, _) => *id != which,
+                });
+                let unplugged = controllers.plugged.remove(player);
+                println!("Controller {} unplugged", unplugged.controller.name());
+            });
         }
-        None
     }
 
-    fn update_state(&mut self, key: u8, state: bool) {
-        self.keys[key as usize] = state;
+    fn move_stick(
+        &mut self,
+        which: u32,
+        axis: Axis,
+        value: i16,
+        chip8: &mut Chip8,
+        keymap: &KeyMap,
+    ) {
+        let (index, directions) = match axis {
+            Axis::LeftX => (0, ["dpleft", "dpright"]),
+            Axis::LeftY => (1, ["dpup", "dpdown"]),
+            _ => return,
+        };
+        let Some(player) = self.player(which) else {
+            return;
+        };
+        let direction = if value <= -STICK_DEAD_ZONE {
+            Some(directions[0])
+        } else if value >= STICK_DEAD_ZONE {
+            Some(directions[1])
+        } else {
+            None
+        };
+        let stick = self.plugged[player].stick[index];
+        if stick == direction {
+            return;
+        }
+        self.update_keys(chip8, keymap, |controllers| {
+            if let Some(button) = stick {
+                controllers.held.remove(&Source::Stick(which, button));
+            }
+            if let Some(button) = direction {
+                controllers.held.insert(Source::Stick(which, button));
+            }
+            controllers.plugged[player].stick[index] = direction;
+        });
     }
 }
 
-pub fn update(sdl_context: &Sdl, keys_state: &mut KeysState) -> Result<(), QuitGameError> {
+pub fn update(
+    sdl_context: &Sdl,
+    chip8: &mut Chip8,
+    keymap: &KeyMap,
+    controllers: &mut Controllers,
+) -> Result<Vec<Hotkey>, QuitGameError> {
     let mut event_pump = sdl_context.event_pump().expect("Failed to get event pump");
+    let mut hotkeys = Vec::new();
 
     while let Some(event) = event_pump.poll_iter().next() {
         match event {
@@ -41,151 +194,52 @@ pub fn update(sdl_context: &Sdl, keys_state: &mut KeysState) -> Result<(), QuitG
             } => return Err(QuitGameError),
 
             Event::KeyDown {
-                keycode: Some(Keycode::Num1),
-                ..
-            } => keys_state.update_state(0x1, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::Num1),
-                ..
-            } => keys_state.update_state(0x1, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::Num2),
-                ..
-            } => keys_state.update_state(0x2, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::Num2),
-                ..
-            } => keys_state.update_state(0x2, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::Num3),
-                ..
-            } => keys_state.update_state(0x3, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::Num3),
-                ..
-            } => keys_state.update_state(0x3, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::Num4),
-                ..
-            } => keys_state.update_state(0xC, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::Num4),
-                ..
-            } => keys_state.update_state(0xC, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::A),
-                ..
-            } => keys_state.update_state(0x4, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::A),
-                ..
-            } => keys_state.update_state(0x4, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::Z),
-                ..
-            } => keys_state.update_state(0x5, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::Z),
-                ..
-            } => keys_state.update_state(0x5, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::E),
-                ..
-            } => keys_state.update_state(0x6, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::E),
-                ..
-            } => keys_state.update_state(0x6, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::R),
-                ..
-            } => keys_state.update_state(0xD, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::R),
-                ..
-            } => keys_state.update_state(0xD, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::Q),
-                ..
-            } => keys_state.update_state(0x7, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::Q),
-                ..
-            } => keys_state.update_state(0x7, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::S),
-                ..
-            } => keys_state.update_state(0x8, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::S),
-                ..
-            } => keys_state.update_state(0x8, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::D),
-                ..
-            } => keys_state.update_state(0x9, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::D),
-                ..
-            } => keys_state.update_state(0x9, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::F),
-                ..
-            } => keys_state.update_state(0xE, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::F),
-                ..
-            } => keys_state.update_state(0xE, false),
-
-            Event::KeyDown {
-                keycode: Some(Keycode::W),
-                ..
-            } => keys_state.update_state(0xA, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::W),
+                keycode: Some(keycode @ (Keycode::F5 | Keycode::F6 | Keycode::F7 | Keycode::F9)),
+                repeat: false,
                 ..
-            } => keys_state.update_state(0xA, false),
-
+            } => hotkeys.push(match keycode {
+                Keycode::F5 => Hotkey::SaveState,
+                Keycode::F6 => Hotkey::PreviousSlot,
+                Keycode::F7 => Hotkey::NextSlot,
+                _ => Hotkey::LoadState,
+            }),
             Event::KeyDown {
-                keycode: Some(Keycode::X),
+                keycode: Some(Keycode::Backspace),
+                repeat: false,
                 ..
-            } => keys_state.update_state(0x0, true),
+            } => hotkeys.push(Hotkey::Rewind(true)),
             Event::KeyUp {
-                keycode: Some(Keycode::X),
+                keycode: Some(Keycode::Backspace),
                 ..
-            } => keys_state.update_state(0x0, false),
+            } => hotkeys.push(Hotkey::Rewind(false)),
 
+            // The CHIP-8 keys, see keymap.rs
             Event::KeyDown {
-                keycode: Some(Keycode::C),
+                keycode: Some(keycode),
                 ..
-            } => keys_state.update_state(0xB, true),
+            } => controllers.hold(Source::Key(keycode.name()), true, chip8, keymap),
             Event::KeyUp {
-                keycode: Some(Keycode::C),
+                keycode: Some(keycode),
                 ..
-            } => keys_state.update_state(0xB, false),
+            } => controllers.hold(Source::Key(keycode.name()), false, chip8, keymap),
 
-            Event::KeyDown {
-                keycode: Some(Keycode::V),
-                ..
-            } => keys_state.update_state(0xF, true),
-            Event::KeyUp {
-                keycode: Some(Keycode::V),
-                ..
-            } => keys_state.update_state(0xF, false),
+            // The controllers, see keymap.rs
+            Event::ControllerDeviceAdded { which, .. } => controllers.plug(which),
+            Event::ControllerDeviceRemoved { which, .. } => {
+                controllers.unplug(which, chip8, keymap)
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                controllers.hold(Source::Button(which, button.string()), true, chip8, keymap)
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                controllers.hold(Source::Button(which, button.string()), false, chip8, keymap)
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => controllers.move_stick(which, axis, value, chip8, keymap),
 
             _ => {}
         };
     }
-    Ok(())
+    Ok(hotkeys)
 }
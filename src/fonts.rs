@@ -0,0 +1,266 @@
+use crate::constants::{BIG_FONT_ADRESS, FONT_ADRESS};
+use crate::launch_options::Platform;
+use crate::memory::Memory;
+
+const SMALL_CHAR_SIZE: u16 = 5; // 4x5 pixels
+const BIG_CHAR_SIZE: u16 = 10; // 8x10 pixels
+
+/// The 16 hexadecimal characters pointed by FX29, each interpreter drew its own.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SmallFont {
+    Vip,
+    Dream6800,
+    Eti660,
+    SuperChip, // also used by CHIP-48 and most modern interpreters
+}
+
+/// The big digits pointed by FX30 on SUPER-CHIP.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BigFont {
+    SuperChip, // only the 10 decimal digits
+    XoChip,    // the 16 hexadecimal characters of Octo
+}
+
+/// The fonts loaded in memory at launch, FX29 and FX30 point I at their characters.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fonts {
+    pub small: SmallFont,
+    pub big: BigFont,
+}
+
+impl Fonts {
+    /// The fonts of the interpreter of each platform.
+    pub fn for_platform(platform: Platform) -> Fonts {
+        Fonts {
+            small: SmallFont::SuperChip,
+            big: match platform {
+                Platform::XoChip => BigFont::XoChip,
+                Platform::Chip8 | Platform::SuperChip => BigFont::SuperChip,
+            },
+        }
+    }
+
+    pub fn load(&self, memory: &mut Memory) {
+        for (i, byte) in self.small.sprites().iter().enumerate() {
+            memory.write(FONT_ADRESS + i as u16, *byte);
+        }
+        for (i, byte) in self.big.sprites().iter().enumerate() {
+            memory.write(BIG_FONT_ADRESS + i as u16, *byte);
+        }
+    }
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Fonts::for_platform(Platform::Chip8)
+    }
+}
+
+impl SmallFont {
+    /// The font named `vip`, `dream6800`, `eti660` or `schip`.
+    pub fn parse(name: &str) -> Result<SmallFont, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" => Ok(SmallFont::Vip),
+            "dream6800" => Ok(SmallFont::Dream6800),
+            "eti660" => Ok(SmallFont::Eti660),
+            "schip" => Ok(SmallFont::SuperChip),
+            _ => Err(format!(
+                "Invalid font: {name} (expected vip, dream6800, eti660 or schip)"
+            )),
+        }
+    }
+
+    pub fn sprites(&self) -> &'static [u8] {
+        match self {
+            SmallFont::Vip => &VIP_FONT,
+            SmallFont::Dream6800 => &DREAM_6800_FONT,
+            SmallFont::Eti660 => &ETI_660_FONT,
+            SmallFont::SuperChip => &SCHIP_FONT,
+        }
+    }
+}
+
+impl BigFont {
+    /// The font named `schip` or `xochip`.
+    pub fn parse(name: &str) -> Result<BigFont, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "schip" => Ok(BigFont::SuperChip),
+            "xochip" => Ok(BigFont::XoChip),
+            _ => Err(format!(
+                "Invalid big font: {name} (expected schip or xochip)"
+            )),
+        }
+    }
+
+    pub fn sprites(&self) -> &'static [u8] {
+        match self {
+            BigFont::SuperChip => &SCHIP_BIG_FONT,
+            BigFont::XoChip => &XO_CHIP_BIG_FONT,
+        }
+    }
+}
+
+/// Where FX29 points I for the low nibble of `char`.
+pub fn char_adress(char: u8) -> u16 {
+    FONT_ADRESS + (char & 0x0F) as u16 * SMALL_CHAR_SIZE
+}
+
+/// Where FX30 points I for the low nibble of `digit`. The SUPER-CHIP font has no A to F,
+/// they point after it like on the HP48.
+pub fn big_char_adress(digit: u8) -> u16 {
+    BIG_FONT_ADRESS + (digit & 0x0F) as u16 * BIG_CHAR_SIZE
+}
+
+// SPRITES
+
+const VIP_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0x70, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// 3 pixels wide
+const DREAM_6800_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// 3 pixels wide
+const ETI_660_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const SCHIP_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const SCHIP_BIG_FONT: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+const XO_CHIP_BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_fonts_have_every_character() {
+        for font in [
+            SmallFont::Vip,
+            SmallFont::Dream6800,
+            SmallFont::Eti660,
+            SmallFont::SuperChip,
+        ] {
+            assert_eq!(font.sprites().len(), 16 * SMALL_CHAR_SIZE as usize);
+        }
+        assert_eq!(
+            BigFont::SuperChip.sprites().len(),
+            10 * BIG_CHAR_SIZE as usize
+        );
+        assert_eq!(BigFont::XoChip.sprites().len(), 16 * BIG_CHAR_SIZE as usize);
+    }
+
+    #[test]
+    fn load_writes_the_characters_where_fx29_and_fx30_point() {
+        let mut memory = Memory::new();
+        let fonts = Fonts {
+            small: SmallFont::Vip,
+            big: BigFont::SuperChip,
+        };
+        fonts.load(&mut memory);
+        let start = char_adress(0xB);
+        assert_eq!(
+            (start..start + 5)
+                .map(|adress| memory.read(adress))
+                .collect::<Vec<_>>(),
+            [0xF0, 0x50, 0x70, 0x50, 0xF0]
+        );
+        assert_eq!(memory.read(big_char_adress(9) + 9), 0x7C);
+        assert_eq!(SmallFont::parse("ETI660"), Ok(SmallFont::Eti660));
+        assert!(BigFont::parse("vip").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::chip8::Chip8;
+use crate::custom_errors::EmulatorError;
+use crate::launch_options::LaunchOptions;
+use crate::screen::Screen;
+
+/// When a headless run stops.
+#[derive(Clone, Copy)]
+pub enum Limit {
+    Frames(u64),
+    Instructions(u64),
+}
+
+/// A key pressed or released by a script before the frame `frame` (from 0).
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Parses a key script like `10:+5 20:-5 30:+A`, where `10:+5` presses the key 5 before the
+/// frame 10 and `20:-5` releases it before the frame 20. The keys are hexadecimal.
+pub fn parse_key_script(script: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+    for event in script.split(|c: char| c.is_whitespace() || c == ',') {
+        if event.is_empty() {
+            continue;
+        }
+        let invalid = || format!("Invalid key event: {event} (expected FRAME:+KEY or FRAME:-KEY)");
+        let (frame, key) = event.split_once(':').ok_or_else(invalid)?;
+        let (pressed, key) = match key.split_at(1.min(key.len())) {
+            ("+", key) => (true, key),
+            ("-", key) => (false, key),
+            _ => return Err(invalid()),
+        };
+        events.push(KeyEvent {
+            frame: frame.parse().map_err(|_| invalid())?,
+            key: u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(invalid)?,
+            pressed,
+        });
+    }
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+/// Runs `chip8` without window or sound until `limit`, sending the key events at their frames.
+/// Stops earlier if the rom exits the interpreter.
+pub fn run(chip8: &mut Chip8, limit: Limit, keys: &[KeyEvent]) -> Result<(), EmulatorError> {
+    let mut keys = keys.iter().peekable();
+    for frame in 0.. {
+        while let Some(event) = keys.next_if(|event| event.frame <= frame) {
+            if event.pressed {
+                chip8.press_key(event.key);
+            } else {
+                chip8.release_key(event.key);
+            }
+        }
+        match limit {
+            Limit::Frames(frames) if frame >= frames => break,
+            Limit::Frames(_) => chip8.run_frame()?,
+            Limit::Instructions(instructions) => {
+                if chip8.run_frame_until(|chip8| chip8.cycles() >= instructions)? {
+                    break;
+                }
+            }
+        }
+        if chip8.has_exited() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The screen as a PNG image, one pixel per CHIP-8 pixel with the colors of `options`.
+pub fn screen_png(screen: &Screen, options: &LaunchOptions) -> Vec<u8> {
+    let colors = [
+        options.pixel_off,
+        options.pixel_on,
+        options.pixel_plane_2,
+        options.pixel_both,
+    ];
+    let (width, height) = screen.resolution();
+
+    // Rows of RGB pixels, each one starting with the filter type 0 (none)
+    let mut image = Vec::with_capacity((1 + 3 * width) * height);
+    for y in 0..height {
+        image.push(0);
+        for x in 0..width {
+            let (r, g, b) = colors[screen.pixel(x as u8, y as u8) as usize];
+            image.extend([r, g, b]);
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    header.extend([8, 2, 0, 0, 0]); // 8 bits per channel, RGB, no interlacing
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&image));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    png.extend(crc32(kind.iter().chain(data)).to_be_bytes());
+}
+
+// A zlib stream with uncompressed blocks, the images are small enough
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        out.push(last as u8);
+        out.extend((block.len() as u16).to_le_bytes());
+        out.extend((!(block.len() as u16)).to_le_bytes());
+        out.extend(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend((b << 16 | a).to_be_bytes());
+    out
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
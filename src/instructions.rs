@@ -1,765 +1,1415 @@
 use rand::Rng;
-use sdl2::render::{Canvas, WindowCanvas};
-use sdl2::video::Window;
 
 use crate::constants::*;
-use crate::custom_errors::NonUsedInstructionError;
-use crate::events::KeysState;
+use crate::custom_errors::EmulatorError;
+use crate::fonts;
+use crate::keys::{KeyWait, KeysState};
 use crate::launch_options::*;
 use crate::memory::Memory;
-use crate::screen::Screen;
-use crate::{display, screen};
+use crate::opcodes::Opcode;
+use crate::quirks::MemoryIncrement;
+use crate::screen;
+use crate::screen::{Screen, HIRES, LORES, VIP_HIRES};
+use crate::stack::Stack;
 
 pub fn decode(
     pc: &mut u16,
-    stack: &mut Vec<u16>,
+    stack: &mut Stack,
     screen: &mut screen::Screen,
-    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
     memory: &mut Memory,
-    keys_state: &KeysState,
-) -> Result<(), NonUsedInstructionError> {
-    let instruction = memory.read_word(*pc);
+    keys_state: &mut KeysState,
+    exited: &mut bool,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    if *pc as usize >= memory.size() && options.quirks.memory_wrap {
+        *pc = (*pc as usize % memory.size()) as u16;
+    }
+    check_bounds(*pc, 2, pc.wrapping_add(2), memory, options)?;
+    let mut instruction = memory.fetch(*pc);
+
+    // The 2-page hires roms of the COSMAC VIP start with a jump over their own interpreter patch,
+    // we switch to 64x64 and skip it
+    if options.platform == Platform::Chip8 && *pc == 0x200 && instruction == 0x1260 {
+        screen.set_resolution(VIP_HIRES);
+        instruction = 0x12C0;
+    }
 
-    *pc += 2;
-    let opcode = (instruction & 0xF000) >> 12;
+    *pc = pc.wrapping_add(2);
+
+    // The instructions that don't exist on this platform are rejected by the opcode table
+    let Some(op) = Opcode::decode(instruction, options.platform) else {
+        return Err(EmulatorError::UnknownOpcode {
+            pc: pc.wrapping_sub(2),
+            instruction,
+        });
+    };
 
-    match opcode {
+    match op {
         // 0x00E0 : Clear screen
+        Opcode::Cls => screen.clear(),
+        // 0x0230 : Clear screen in the 64x64 hires mode of the COSMAC VIP
+        Opcode::Sys(NNN) => i0NNN(NNN, *pc, instruction, screen)?,
         // 0x00EE : Return from subroutine
-        0 => i0(instruction, pc, stack, screen, canvas)?,
+        Opcode::Ret => i00EE(pc, stack)?,
+        // 0x00CN SUPER-CHIP : Scroll the screen down by N pixels
+        Opcode::ScrollDown(N) => screen.scroll_down(N as usize),
+        // 0x00DN XO-CHIP : Scroll the selected planes up by N pixels
+        Opcode::ScrollUp(N) => screen.scroll_up(N as usize),
+        // 0x00FB SUPER-CHIP : Scroll the screen right by 4 pixels
+        Opcode::ScrollRight => screen.scroll_right(4),
+        // 0x00FC SUPER-CHIP : Scroll the screen left by 4 pixels
+        Opcode::ScrollLeft => screen.scroll_left(4),
+        // 0x00FD SUPER-CHIP : Exit the interpreter
+        Opcode::Exit => *exited = true,
+        // 0x00FE SUPER-CHIP : Disable hires mode (64x32)
+        Opcode::Lores => screen.set_resolution(LORES),
+        // 0x00FF SUPER-CHIP : Enable hires mode (128x64)
+        Opcode::Hires => screen.set_resolution(HIRES),
         // 0x1NNN jump to adress 0xNNN
-        1 => i1(instruction, pc),
+        Opcode::Jump(NNN) => *pc = NNN,
         // 0x2NNN call subroutine at 0xNNN
-        2 => i2(instruction, pc, stack),
+        Opcode::Call(NNN) => i2(NNN, pc, stack)?,
         // 0x3XNN skip next instruction if VX == NN
+        Opcode::SkipEqByte(X, NN) => i34(X, NN, true, pc, memory, options),
         // 0x4XNN skip next instruction if VX != NN
-        3 | 4 => i34(instruction, pc, memory, opcode),
+        Opcode::SkipNeByte(X, NN) => i34(X, NN, false, pc, memory, options),
+        // 0x5XY2 XO-CHIP : store VX through VY in memory starting at address I
+        Opcode::SaveRange(X, Y) => i5_23(X, Y, true, *pc, memory, options)?,
+        // 0x5XY3 XO-CHIP : load VX through VY from memory starting at address I
+        Opcode::LoadRange(X, Y) => i5_23(X, Y, false, *pc, memory, options)?,
         // 0x5XY0 skip next instruction if VX == VY
+        Opcode::SkipEq(X, Y) => i59(X, Y, true, pc, memory, options),
         // 0x9XY0 skip next instruction if VX != VY
-        5 | 9 => i59(instruction, pc, opcode, memory)?,
+        Opcode::SkipNe(X, Y) => i59(X, Y, false, pc, memory, options),
         // 0x6XNN set register VX to 0xNN
-        6 => i6(instruction, *pc, memory),
+        Opcode::SetByte(X, NN) => memory.write_register(X as usize, NN),
         // 0x7XNN add 0xNN to register VX (carry flag is not changed)
-        7 => i7(instruction, *pc, memory),
-        0x8 => {
-            match instruction & 0x000F {
-                // 0x8XY0 set VX to VY
-                0 => i8_0(memory, *pc, &instruction),
-                // 0x8XY1 set VX to VX | VY
-                // 0x8XY2 set VX to VX & VY
-                // 0x8XY3 set VX to VX ^ VY
-                1..=3 => i8_123(instruction, *pc, memory),
-                // 0x8XY4 Add VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't
-                // 0x8XY5 Set VX to VX - VY, set VF to 0 when there's a borrow, and 1 when there isn't
-                // 0x8XY7           VY - VX
-                4 | 5 | 7 => i8_457(instruction, *pc, memory),
-                // 0x8XY6 OLD : VX is set to VY and shifted right by 1. VF is set to the bit shifted out
-                //        NEW : VX is shifted right by 1. VF is set to the bit shifted out
-                // 0x8XYE OLD : VX is set to VY and shifted left by 1. VF is set to the bit shifted out
-                //        NEW : VX is shifted left by 1. VF is set to the bit shifted out
-                6 | 0xE => i8_6E(instruction, *pc, memory),
-                _ => {
-                    return Err(NonUsedInstructionError {
-                        pc: *pc - 2,
-                        instruction,
-                    })
-                }
-            }
-        }
+        Opcode::AddByte(X, NN) => i7(X, NN, memory),
+        // 0x8XY0 set VX to VY
+        Opcode::Set(X, Y) => memory.write_register(X as usize, memory.read_register(Y as usize)),
+        // 0x8XY1 set VX to VX | VY
+        // 0x8XY2 set VX to VX & VY
+        // 0x8XY3 set VX to VX ^ VY
+        //        OLD : VF is reset to 0
+        Opcode::Or(X, Y) => i8_123(X, Y, |x, y| x | y, memory, options),
+        Opcode::And(X, Y) => i8_123(X, Y, |x, y| x & y, memory, options),
+        Opcode::Xor(X, Y) => i8_123(X, Y, |x, y| x ^ y, memory, options),
+        // 0x8XY4 Add VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't
+        Opcode::Add(X, Y) => i8_4(X, Y, memory),
+        // 0x8XY5 Set VX to VX - VY, set VF to 0 when there's a borrow, and 1 when there isn't
+        // 0x8XY7           VY - VX
+        Opcode::Sub(X, Y) => i8_57(X, X, Y, memory),
+        Opcode::SubN(X, Y) => i8_57(X, Y, X, memory),
+        // 0x8XY6 OLD : VX is set to VY and shifted right by 1. VF is set to the bit shifted out
+        //        NEW : VX is shifted right by 1. VF is set to the bit shifted out
+        // 0x8XYE OLD : VX is set to VY and shifted left by 1. VF is set to the bit shifted out
+        //        NEW : VX is shifted left by 1. VF is set to the bit shifted out
+        Opcode::ShiftRight(X, Y) => i8_6E(X, Y, false, memory, options),
+        Opcode::ShiftLeft(X, Y) => i8_6E(X, Y, true, memory, options),
         // 0xANNN set I to 0x0NNN
-        0xA => iA(memory, *pc, instruction),
+        Opcode::SetI(NNN) => memory.write_adress(NNN),
         // 0xBNNN OLD: jump to 0x0NNN + V0
         // 0xBXNN NEW: jump to 0xXNN + VX
-        0xB => iB(instruction, pc, memory),
+        Opcode::JumpOffset(X, NNN) => iB(X, NNN, pc, memory, options),
         // 0xCXNN set VX to random number and binary-AND's it with NN
-        0xC => iC(instruction, *pc, memory),
+        Opcode::Random(X, NN) => iC(X, NN, memory),
         // 0xDXYN display sprite at (VX, VY) with width 8 and height N
-        0xD => iD(memory, *pc, instruction, screen, canvas),
+        // 0xDXY0 SUPER-CHIP : display a 16x16 sprite at (VX, VY)
+        // XO-CHIP : the sprite is drawn in each selected plane, the data of plane 2 follows the one of plane 1
+        Opcode::Draw(X, Y, N) => iD(X, Y, N, *pc, memory, screen, options)?,
         // 0xEX9E skip next instruction if key with the value of VX is pressed
+        Opcode::SkipKey(X) => iE(X, true, pc, memory, keys_state, options),
         // 0xEXA1 skip next instruction if key with the value of VX is not pressed
-        0xE => iE(instruction, pc, memory, keys_state),
-        0xF => {
-            match instruction & 0x00FF {
-                // 0xFX07 set VX to the value of the delay timer
-                0x0007 => iF_07(instruction, *pc, memory),
-                // 0xFX0A wait for a key press, store the value of the key in VX
-                0x000A => iF_0A(instruction, pc, memory, keys_state),
-                // 0xFX15 set the delay timer to VX
-                // 0xFX18 set the sound timer to VX
-                0x0015 | 0x0018 => iF_1518(instruction, pc, memory),
-                // 0xFX1E add VX to I with carry flag if CB_BNNN = NEW
-                0x001E => iF_1E(instruction, pc, memory),
-                // 0xFX29 set I to the location of the sprite for the character in VX
-                0x0029 => iF_29(instruction, *pc, memory),
-                // 0xFX33 store the binary-coded decimal representation of VX at the addresses I, I+1, and I+2
-                0x0033 => iF_33(instruction, *pc, memory),
-                // 0xFX55 store V0 through VX in memory starting at address I
-                // 0xFX65 store memory through V0 to VX starting at address I
-                0x0055 | 0x0065 => iF_5565(instruction, *pc, memory),
-                _ => {
-                    return Err(NonUsedInstructionError {
-                        pc: *pc - 2,
-                        instruction,
-                    })
-                }
-            }
-        }
-        _ => {
-            unreachable!();
-        }
+        Opcode::SkipNotKey(X) => iE(X, false, pc, memory, keys_state, options),
+        // 0xF000 NNNN XO-CHIP : set I to the 16 bits adress NNNN
+        Opcode::SetILong => iF_000(pc, memory, options)?,
+        // 0xFN01 XO-CHIP : select the planes N (bitmask) for drawing, clearing and scrolling
+        Opcode::Planes(N) => screen.select_planes(N),
+        // 0xF002 XO-CHIP : load the 16 bytes at I into the audio pattern buffer
+        Opcode::Audio => iF_002(*pc, memory, options)?,
+        // 0xFX07 set VX to the value of the delay timer
+        Opcode::GetDelay(X) => memory.write_register(X as usize, memory.read_delay_timer()),
+        // 0xFX0A wait for a key press, store the value of the key in VX
+        Opcode::WaitKey(X) => iF_0A(X, pc, memory, keys_state, options),
+        // 0xFX15 set the delay timer to VX
+        Opcode::SetDelay(X) => memory.write_delay_timer(memory.read_register(X as usize)),
+        // 0xFX18 set the sound timer to VX
+        Opcode::SetSound(X) => memory.write_sound_timer(memory.read_register(X as usize)),
+        // 0xFX1E add VX to I with carry flag if CB_BNNN = NEW
+        Opcode::AddI(X) => iF_1E(X, *pc, memory, options)?,
+        // 0xFX29 set I to the location of the sprite for the character in VX
+        Opcode::Font(X) => iF_29(X, memory),
+        // 0xFX30 SUPER-CHIP : set I to the location of the big sprite for the digit in VX
+        Opcode::BigFont(X) => iF_30(X, memory),
+        // 0xFX3A XO-CHIP : set the pitch of the audio pattern to VX
+        Opcode::Pitch(X) => memory.write_pitch(memory.read_register(X as usize)),
+        // 0xFX33 store the binary-coded decimal representation of VX at the addresses I, I+1, and I+2
+        Opcode::Bcd(X) => iF_33(X, *pc, memory, options)?,
+        // 0xFX55 store V0 through VX in memory starting at address I
+        Opcode::Store(X) => iF_5565(X, true, *pc, memory, options)?,
+        // 0xFX65 store memory through V0 to VX starting at address I
+        Opcode::Load(X) => iF_5565(X, false, *pc, memory, options)?,
+        // 0xFX75 SUPER-CHIP : store V0 through VX in the RPL user flags
+        Opcode::StoreFlags(X) => iF_7585(X, true, memory),
+        // 0xFX85 SUPER-CHIP : load V0 through VX from the RPL user flags
+        Opcode::LoadFlags(X) => iF_7585(X, false, memory),
     }
 
     Ok(())
 }
 
-pub fn i0(
+// The instruction after a skip is 4 bytes long if it is the XO-CHIP F000 NNNN
+fn skip_next_instruction(pc: &mut u16, memory: &Memory, options: &LaunchOptions) {
+    if options.platform == Platform::XoChip && memory.peek_word(*pc) == 0xF000 {
+        *pc = pc.wrapping_add(4);
+    } else {
+        *pc = pc.wrapping_add(2);
+    }
+}
+
+// The `len` bytes from `start` accessed by the instruction at `pc` - 2 wrap around the end of the memory,
+// or stop the emulation without the memory_wrap quirk
+fn check_bounds(
+    start: u16,
+    len: usize,
+    pc: u16,
+    memory: &Memory,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    if !options.quirks.memory_wrap && start as usize + len > memory.size() {
+        return Err(EmulatorError::OutOfBounds {
+            pc: pc.wrapping_sub(2),
+            adress: memory.size().max(start as usize),
+        });
+    }
+    Ok(())
+}
+
+// Only 0230 is known, the other machine code routines can't be emulated
+pub fn i0NNN(
+    NNN: u16,
+    pc: u16,
     instruction: u16,
-    pc: &mut u16,
-    stack: &mut Vec<u16>,
-    screen: &mut screen::Screen,
-    canvas: &mut Canvas<Window>,
-) -> Result<(), NonUsedInstructionError> {
-    match instruction {
-        // Clear screen
-        0x00E0 => {
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Screen clearing",
-                    *pc - 2,
-                    instruction
-                );
-            }
-            screen.clear();
-            display::clear_screen(canvas)
-        }
-        // Return from subroutine
-        0x00EE => {
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Returning from subroutine",
-                    *pc - 2,
-                    instruction
-                );
-            }
-            *pc = stack.pop().unwrap();
-        }
-        _ => {
-            return Err(NonUsedInstructionError {
-                pc: *pc,
-                instruction,
-            });
-        }
+    screen: &mut Screen,
+) -> Result<(), EmulatorError> {
+    if NNN != 0x230 || screen.resolution() != VIP_HIRES {
+        return Err(EmulatorError::UnknownOpcode {
+            pc: pc.wrapping_sub(2),
+            instruction,
+        });
     }
+    screen.clear();
     Ok(())
 }
 
-pub fn i1(instruction: u16, pc: &mut u16) {
-    let NNN = instruction & 0x0FFF;
+pub fn i00EE(pc: &mut u16, stack: &mut Stack) -> Result<(), EmulatorError> {
+    *pc = stack.pop().ok_or(EmulatorError::StackUnderflow {
+        pc: pc.wrapping_sub(2),
+    })?;
+    Ok(())
+}
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Jumping to adress 0x{:03X}",
-            *pc - 2,
-            instruction,
-            NNN
-        );
+pub fn i2(NNN: u16, pc: &mut u16, stack: &mut Stack) -> Result<(), EmulatorError> {
+    if !stack.push(*pc) {
+        return Err(EmulatorError::StackOverflow {
+            pc: pc.wrapping_sub(2),
+        });
     }
     *pc = NNN;
+    Ok(())
 }
 
-pub fn i2(instruction: u16, pc: &mut u16, stack: &mut Vec<u16>) {
-    let NNN = instruction & 0x0FFF;
+pub fn i34(X: u8, NN: u8, equal: bool, pc: &mut u16, memory: &mut Memory, options: &LaunchOptions) {
+    let VX = memory.read_register(X as usize);
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Calling subroutine at 0x{:03X}",
-            *pc - 2,
-            instruction,
-            NNN
-        );
+    if (VX == NN) == equal {
+        skip_next_instruction(pc, memory, options);
     }
-    stack.push(*pc);
-    *pc = NNN;
 }
 
-pub fn i34(instruction: u16, pc: &mut u16, memory: &mut Memory, opcode: u16) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let NN = (instruction & 0x00FF) as usize;
-
-    let VX = memory.read_register(X);
-
-    if DEBUG {
-        match (opcode, VX == NN as u8) {
-            (3, true) => println!(
-                "0x{:03X} | 0x{:04X} | Skipping next instruction because V{:X} == 0x{:02X}",
-                *pc - 2,
-                instruction,
-                X,
-                NN
-            ),
-            (3, false) => println!(
-                "0x{:03X} | 0x{:04X} | Not skipping next instruction because V{:X} != 0x{:02X}",
-                *pc - 2,
-                instruction,
-                X,
-                NN
-            ),
-            (4, true) => println!(
-                "0x{:03X} | 0x{:04X} | Not skipping next instruction because V{:X} == 0x{:02X}",
-                *pc - 2,
-                instruction,
-                X,
-                NN
-            ),
-            (4, false) => println!(
-                "0x{:03X} | 0x{:04X} | Skipping next instruction because V{:X} != 0x{:02X}",
-                *pc - 2,
-                instruction,
-                X,
-                NN
-            ),
-            _ => (),
-        }
+pub fn i59(X: u8, Y: u8, equal: bool, pc: &mut u16, memory: &mut Memory, options: &LaunchOptions) {
+    let VX = memory.read_register(X as usize);
+    let VY = memory.read_register(Y as usize);
+
+    if (VX == VY) == equal {
+        skip_next_instruction(pc, memory, options);
     }
+}
 
-    *pc += if (opcode == 3 && VX == NN as u8) || (opcode == 4 && VX != NN as u8) {
-        2
+pub fn i5_23(
+    X: u8,
+    Y: u8,
+    save: bool,
+    pc: u16,
+    memory: &mut Memory,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    let (X, Y) = (X as usize, Y as usize);
+    let I = memory.read_adress();
+
+    // The registers can be in reverse order (X > Y), I is not incremented
+    let registers: Vec<usize> = if X <= Y {
+        (X..=Y).collect()
     } else {
-        0
+        (Y..=X).rev().collect()
     };
+    check_bounds(I, registers.len(), pc, memory, options)?;
+    for (i, register) in registers.into_iter().enumerate() {
+        let adress = I.wrapping_add(i as u16);
+        if save {
+            memory.write(adress, memory.read_register(register));
+        } else {
+            memory.write_register(register, memory.read(adress));
+        }
+    }
+    Ok(())
 }
 
-pub fn i59(
-    instruction: u16,
-    pc: &mut u16,
-    opcode: u16,
+pub fn i7(X: u8, NN: u8, memory: &mut Memory) {
+    let VX = memory.read_register(X as usize);
+    memory.write_register(X as usize, VX.wrapping_add(NN));
+}
+
+pub fn i8_123(
+    X: u8,
+    Y: u8,
+    operation: fn(u8, u8) -> u8,
     memory: &mut Memory,
-) -> Result<(), NonUsedInstructionError> {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let Y = ((instruction & 0x00F0) >> 4) as usize;
+    options: &LaunchOptions,
+) {
+    let VX = memory.read_register(X as usize);
+    let VY = memory.read_register(Y as usize);
+
+    memory.write_register(X as usize, operation(VX, VY));
+    // The COSMAC VIP interpreter did the logic operations with VF as a scratch register
+    if options.quirks.vf_reset {
+        memory.write_register(0xF, 0);
+    }
+}
+
+pub fn i8_4(X: u8, Y: u8, memory: &mut Memory) {
+    let VX = memory.read_register(X as usize);
+    let VY = memory.read_register(Y as usize);
+
+    let (result, carry) = VX.overflowing_add(VY);
+    // VF is written last, so it holds the flag even when it is VX
+    memory.write_register(X as usize, result);
+    memory.write_register(0xF, carry as u8);
+}
+
+// VX = minuend - subtrahend, the registers are X and Y for 8XY5, Y and X for 8XY7
+pub fn i8_57(X: u8, minuend: u8, subtrahend: u8, memory: &mut Memory) {
+    let minuend = memory.read_register(minuend as usize);
+    let subtrahend = memory.read_register(subtrahend as usize);
 
-    let VX = memory.read_register(X);
-    let VY = memory.read_register(Y);
+    let (result, borrow) = minuend.overflowing_sub(subtrahend);
+    // VF is written last, so it holds the flag even when it is VX
+    memory.write_register(X as usize, result);
+    memory.write_register(0xF, !borrow as u8);
+}
 
-    if opcode == 5 || opcode == 9 {
-        let condition_met = if opcode == 5 { VX == VY } else { VX != VY };
+pub fn i8_6E(X: u8, Y: u8, left: bool, memory: &mut Memory, options: &LaunchOptions) {
+    // COSMAC VIP : VX is set to VY then shifted, CHIP-48 and after : VX is shifted
+    let source = if !options.quirks.shifting {
+        memory.read_register(Y as usize)
+    } else {
+        memory.read_register(X as usize)
+    };
+
+    let (result, flag) = if left {
+        (source << 1, source >> 7)
+    } else {
+        (source >> 1, source & 0x1)
+    };
+    // VF is written last, so it holds the bit shifted out even when it is VX
+    memory.write_register(X as usize, result);
+    memory.write_register(0xF, flag);
+}
 
-        if condition_met {
-            *pc += 2;
+pub fn iB(X: u8, NNN: u16, pc: &mut u16, memory: &mut Memory, options: &LaunchOptions) {
+    if !options.quirks.jumping {
+        // 0xBNNN jump to 0x0NNN + V0
+        let V0 = memory.read_register(0);
+        *pc = NNN + V0 as u16;
+    } else {
+        // 0xBXNN jump to 0xXNN + VX
+        let VX = memory.read_register(X as usize);
+        *pc = NNN + VX as u16;
+    }
+}
+
+pub fn iC(X: u8, NN: u8, memory: &mut Memory) {
+    let mut rng = rand::thread_rng();
+    let random: u8 = rng.gen();
+
+    memory.write_register(X as usize, random & NN);
+}
+
+pub fn iD(
+    X: u8,
+    Y: u8,
+    N: u8,
+    pc: u16,
+    memory: &mut Memory,
+    screen: &mut Screen,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    let VX = memory.read_register(X as usize);
+    let VY = memory.read_register(Y as usize);
+    let N = N as u16;
+
+    // DXY0 draws a 16x16 sprite on SUPER-CHIP and XO-CHIP, 2 bytes per row
+    let (sprite_width, sprite_height) = if N == 0 && options.platform.has_schip_instructions() {
+        (16, 16)
+    } else {
+        (8, N)
+    };
+    let sprite_size = sprite_height * sprite_width / 8; // in bytes
+
+    // Each selected plane gets its own sprite, one after the other in memory
+    let mut sprite_adress = memory.read_adress();
+    let planes = (screen.selected_planes() & 0b11).count_ones() as usize;
+    check_bounds(
+        sprite_adress,
+        planes * sprite_size as usize,
+        pc,
+        memory,
+        options,
+    )?;
+
+    let (width, height) = (screen.width(), screen.height());
+    memory.write_register(0xF, 0);
+    for plane in [1, 2] {
+        if screen.selected_planes() & plane == 0 {
+            continue;
         }
 
-        if DEBUG {
-            let skip_action = if condition_met {
-                "Skipping"
+        let mut cX = VX as usize % width; // coord X
+        let mut cY = VY as usize % height; // coord Y
+        let ccX = cX;
+
+        'rows: for i in 0..sprite_height {
+            let row = if sprite_width == 16 {
+                memory.read_word(sprite_adress.wrapping_add(2 * i))
             } else {
-                "Not skipping"
+                (memory.read(sprite_adress.wrapping_add(i)) as u16) << 8
             };
-            let condition_text = if opcode == 5 { "==" } else { "!=" };
-            println!(
-                "0x{:03X} | 0x{:04X} | {skip_action} next instruction because V{:X} {condition_text} V{:X}",
-                *pc - 2,
-                instruction,
-                X,
-                Y
-            );
+            'columns: for j in 0..sprite_width {
+                let pixel = (row >> (15 - j)) & 0x1;
+                if pixel == 1 && screen.flip(cX as u8, cY as u8, plane) {
+                    memory.write_register(0xF, 1);
+                }
+
+                cX += 1;
+                if cX == width {
+                    if options.quirks.clipping {
+                        break 'columns;
+                    }
+                    cX = 0;
+                }
+            }
+            cX = ccX;
+            cY += 1;
+            if cY == height {
+                if options.quirks.clipping {
+                    break 'rows;
+                }
+                cY = 0;
+            }
         }
-        return Ok(());
+        sprite_adress = sprite_adress.wrapping_add(sprite_size);
     }
-
-    Err(NonUsedInstructionError {
-        pc: *pc - 2,
-        instruction,
-    })
+    Ok(())
 }
 
-pub fn i6(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let NN = (instruction & 0x00FF) as usize;
+// Skips the next instruction if the key in VX is in the state `pressed`
+pub fn iE(
+    X: u8,
+    pressed: bool,
+    pc: &mut u16,
+    memory: &mut Memory,
+    keys_state: &KeysState,
+    options: &LaunchOptions,
+) {
+    let VX = memory.read_register(X as usize);
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Setting register V{:01X} to 0x{:02X} = {NN}",
-            pc - 2,
-            instruction,
-            X,
-            NN
-        );
+    // Only the low nibble selects a key
+    if keys_state.read_state(VX & 0x0F) == pressed {
+        skip_next_instruction(pc, memory, options);
     }
+}
 
-    memory.write_register(X, NN as u8);
+pub fn iF_000(
+    pc: &mut u16,
+    memory: &mut Memory,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    check_bounds(*pc, 2, *pc, memory, options)?;
+    let NNNN = memory.fetch(*pc);
+
+    memory.write_adress(NNNN);
+    *pc = pc.wrapping_add(2);
+    Ok(())
 }
 
-pub fn i7(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let NN = (instruction & 0x00FF) as usize;
+pub fn iF_002(pc: u16, memory: &mut Memory, options: &LaunchOptions) -> Result<(), EmulatorError> {
+    let I = memory.read_adress();
+    let mut pattern = [0; 16];
+    check_bounds(I, pattern.len(), pc, memory, options)?;
+    for (i, byte) in pattern.iter_mut().enumerate() {
+        *byte = memory.read(I.wrapping_add(i as u16));
+    }
+    memory.write_audio_pattern(pattern);
+    Ok(())
+}
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Adding 0x{:02X} to register V{:01X}",
-            pc - 2,
-            instruction,
-            NN,
-            X
-        );
+pub fn iF_0A(
+    X: u8,
+    pc: &mut u16,
+    memory: &mut Memory,
+    keys_state: &mut KeysState,
+    options: &LaunchOptions,
+) {
+    // The cpu is halted by executing FX0A again until a key is pressed after it started waiting,
+    // the timers keep counting down meanwhile. The COSMAC VIP stored the key once released.
+    match keys_state.wait() {
+        KeyWait::Idle => {
+            keys_state.set_wait(KeyWait::Press);
+            *pc = pc.wrapping_sub(2);
+        }
+        KeyWait::Pressed(key) if !options.quirks.key_release => {
+            keys_state.set_wait(KeyWait::Idle);
+            memory.write_register(X as usize, key);
+        }
+        KeyWait::Released(key) => {
+            keys_state.set_wait(KeyWait::Idle);
+            memory.write_register(X as usize, key);
+        }
+        KeyWait::Press | KeyWait::Pressed(_) => *pc = pc.wrapping_sub(2),
     }
+}
 
-    let VX = memory.read_register(X) as usize;
-    memory.write_register(X, (VX + NN) as u8);
+pub fn iF_1E(
+    X: u8,
+    pc: u16,
+    memory: &mut Memory,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    let VX = memory.read_register(X as usize);
+    let new_I = memory.read_adress() as usize + VX as usize;
+    let overflow = new_I >= memory.size();
+    if overflow && !options.quirks.memory_wrap {
+        return Err(EmulatorError::OutOfBounds {
+            pc: pc.wrapping_sub(2),
+            adress: new_I,
+        });
+    }
+    memory.write_adress((new_I % memory.size()) as u16);
+    // The Amiga interpreter set VF on the overflow, and cleared it otherwise
+    if options.quirks.i_overflow {
+        memory.write_register(0xF, overflow as u8);
+    }
+    Ok(())
 }
 
-pub fn i8_0(memory: &mut Memory, pc: u16, instruction: &u16) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let Y = ((instruction & 0x00F0) >> 4) as usize;
+pub fn iF_29(X: u8, memory: &mut Memory) {
+    let char_0x = memory.read_register(X as usize) & 0x0F;
+    memory.write_adress(fonts::char_adress(char_0x));
+}
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Setting register V{:01X} to V{:01X}",
-            pc - 2,
-            instruction,
-            X,
-            Y
-        );
-    }
+pub fn iF_30(X: u8, memory: &mut Memory) {
+    let digit = memory.read_register(X as usize) & 0x0F;
+    memory.write_adress(fonts::big_char_adress(digit));
+}
 
-    let VY = memory.read_register(Y);
-    memory.write_register(X, VY);
+pub fn iF_33(
+    X: u8,
+    pc: u16,
+    memory: &mut Memory,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    let VX = memory.read_register(X as usize);
+    let (digit_1, digit_2, digit_3) = (VX / 100, (VX / 10) % 10, VX % 10);
+    let I = memory.read_adress();
+    check_bounds(I, 3, pc, memory, options)?;
+    memory.write(I, digit_1);
+    memory.write(I.wrapping_add(1), digit_2);
+    memory.write(I.wrapping_add(2), digit_3);
+    Ok(())
 }
 
-pub fn i8_123(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let Y = ((instruction & 0x00F0) >> 4) as usize;
-
-    let VX = memory.read_register(X);
-    let VY = memory.read_register(Y);
-    match instruction & 0x000F {
-        1 => {
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Setting register V{:01X} to V{:01X} | V{:01X}",
-                    pc - 2,
-                    instruction,
-                    X,
-                    X,
-                    Y
-                );
-            }
-            memory.write_register(X, VX | VY);
-        }
-        2 => {
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Setting register V{:01X} to V{:01X} & V{:01X}",
-                    pc - 2,
-                    instruction,
-                    X,
-                    X,
-                    Y
-                );
-            }
-            memory.write_register(X, VX & VY);
+pub fn iF_5565(
+    X: u8,
+    store: bool,
+    pc: u16,
+    memory: &mut Memory,
+    options: &LaunchOptions,
+) -> Result<(), EmulatorError> {
+    let X = X as usize;
+    let I = memory.read_adress();
+    check_bounds(I, X + 1, pc, memory, options)?;
+    for i in (0..NB_REGISTERS).take(X + 1) {
+        let iu16 = i as u16;
+        if store {
+            let Vi = memory.read_register(i);
+            memory.write(I.wrapping_add(iu16), Vi);
+        } else {
+            let future_Vi = memory.read(I.wrapping_add(iu16));
+            memory.write_register(i, future_Vi);
         }
-        3 => {
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Setting register V{:01X} to V{:01X} ^ V{:01X}",
-                    pc - 2,
-                    instruction,
-                    X,
-                    X,
-                    Y
-                );
-            }
-            memory.write_register(X, VX ^ VY);
+    }
+    match options.quirks.memory {
+        MemoryIncrement::None => {}
+        MemoryIncrement::X => memory.write_adress(I.wrapping_add(X as u16)),
+        MemoryIncrement::XPlusOne => memory.write_adress(I.wrapping_add(X as u16 + 1)),
+    }
+    Ok(())
+}
+
+pub fn iF_7585(X: u8, store: bool, memory: &mut Memory) {
+    for i in 0..=X as usize {
+        if store {
+            memory.write_rpl_flag(i, memory.read_register(i));
+        } else {
+            memory.write_register(i, memory.read_rpl_flag(i));
         }
-        _ => unreachable!(),
     }
 }
 
-pub fn i8_457(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let Y = ((instruction & 0x00F0) >> 4) as usize;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    // The state that decode works on, the instructions are written at pc before being executed
+    struct Machine {
+        pc: u16,
+        stack: Stack,
+        screen: Screen,
+        memory: Memory,
+        keys_state: KeysState,
+        exited: bool,
+        options: LaunchOptions,
+    }
 
-    let VX = memory.read_register(X);
-    let VY = memory.read_register(Y);
-    match instruction & 0x000F {
-        // 0x8XY4 Add VY to VX. VF is set to 1 when there's a carry, and to 0 when there isn't
-        4 => {
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Adding V{:01X} to V{:01X} with carry flag to VF",
-                    pc - 2,
-                    instruction,
-                    Y,
-                    X
-                );
-            }
-            let (result, carry) = VX.overflowing_add(VY);
-            memory.write_register(X, result);
-            memory.write_register(0xF, carry as u8);
-        }
-        // 0x8XY5 Set VX to VX - VY, set VF to 0 when there's a borrow, and 1 when there isn't
-        // 0x8XY7           VY - VX
-        5 | 7 => {
-            let (VX, VY) = if instruction & 0x000F == 5 {
-                if DEBUG {
-                    println!("0x{:03X} | 0x{:04X} | Subtracting V{:01X} from V{:01X} with borrow flag to VF", pc-2, instruction, Y, X);
-                }
-                (VX, VY)
-            } else {
-                if DEBUG {
-                    println!("0x{:03X} | 0x{:04X} | Subtracting V{:01X} from V{:01X} with borrow flag to VF", pc-2, instruction, X, Y);
-                }
-                (VY, VX)
+    impl Machine {
+        fn new(platform: Platform) -> Machine {
+            let options = LaunchOptions {
+                platform,
+                quirks: Quirks::for_platform(platform),
+                ..LaunchOptions::default()
             };
-            let result = VX as isize - VY as isize;
-            if result < 0 {
-                memory.write_register(0xF, 0);
+            let size = if platform == Platform::XoChip {
+                XO_MEMORY_SIZE
             } else {
-                memory.write_register(0xF, 1);
+                MEMORY_SIZE
+            };
+            Machine {
+                pc: 0x200,
+                stack: Stack::new(options.quirks.stack_depth as usize),
+                screen: Screen::new(),
+                memory: Memory::with_size(size),
+                keys_state: KeysState::new(),
+                exited: false,
+                options,
             }
-            memory.write_register(X, (result % 255) as u8);
         }
-        _ => unreachable!(),
-    }
-}
 
-pub fn i8_6E(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let Y = ((instruction & 0x00F0) >> 4) as usize;
+        fn chip8() -> Machine {
+            Machine::new(Platform::Chip8)
+        }
 
-    let VX = memory.read_register(X);
-    let VY = memory.read_register(Y);
-    memory.write_register(0xF, VX & 0x1);
+        fn run(&mut self, instruction: u16) -> Result<(), EmulatorError> {
+            self.memory.write(self.pc, (instruction >> 8) as u8);
+            self.memory
+                .write(self.pc.wrapping_add(1), instruction as u8);
+            decode(
+                &mut self.pc,
+                &mut self.stack,
+                &mut self.screen,
+                &mut self.memory,
+                &mut self.keys_state,
+                &mut self.exited,
+                &self.options,
+            )
+        }
 
-    if instruction & 0x000F == 6 {
-        if CB_8XY_ == CB::OLD {
-            // VX is set to VY and shifted right by 1. VF is set to the bit shifted out
-            if DEBUG {
-                println!("0x{:03X} | 0x{:04X} | Setting V{:01X} to V{:01X} and shifting it right by 1 with bit shifted out to VF", pc-2, instruction, X, Y);
-            }
-            memory.write_register(X, VY >> 1);
-        } else if CB_8XY_ == CB::NEW {
-            // VX is shifted right by 1. VF is set to the bit shifted out
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Shifting V{:01X} right by 1 with bit shifted out to VF",
-                    pc - 2,
-                    instruction,
-                    X
-                );
-            }
-            memory.write_register(X, VX >> 1);
+        fn exec(&mut self, instruction: u16) {
+            self.run(instruction).unwrap();
         }
-    } else if instruction & 0x000F == 0xE {
-        if CB_8XY_ == CB::OLD {
-            // VX is set to VY and shifted left by 1. VF is set to the bit shifted out
-            if DEBUG {
-                println!("0x{:03X} | 0x{:04X} | Setting V{:01X} to V{:01X} and shifting it left by 1 with bit shifted out to VF", pc-2, instruction, X, Y);
-            }
-            memory.write_register(X, VY << 1);
-        } else if CB_8XY_ == CB::NEW {
-            // VX is shifted left by 1. VF is set to the bit shifted out
-            if DEBUG {
-                println!(
-                    "0x{:03X} | 0x{:04X} | Shifting V{:01X} left by 1 with bit shifted out to VF",
-                    pc - 2,
-                    instruction,
-                    X
-                );
-            }
-            memory.write_register(X, VX << 1);
+
+        fn v(&self, x: usize) -> u8 {
+            self.memory.read_register(x)
+        }
+
+        fn set_v(&mut self, x: usize, value: u8) {
+            self.memory.write_register(x, value);
+        }
+
+        fn pixels_on(&self) -> usize {
+            self.screen
+                .iter_coords()
+                .filter(|(x, y)| self.screen.is_on(*x, *y))
+                .count()
         }
     }
-}
 
-pub fn iA(memory: &mut Memory, pc: u16, instruction: u16) {
-    let NNN = instruction & 0x0FFF;
+    // 0NNN
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Setting I to 0x{:03X}",
-            pc - 2,
-            instruction,
-            NNN
+    #[test]
+    fn i0_clears_the_screen() {
+        let mut m = Machine::chip8();
+        m.screen.flip(3, 4, 1);
+        m.exec(0x00E0);
+        assert_eq!(m.pixels_on(), 0);
+        assert_eq!(m.pc, 0x202);
+    }
+
+    #[test]
+    fn i0_returns_from_subroutine() {
+        let mut m = Machine::chip8();
+        assert!(m.stack.push(0x345));
+        m.exec(0x00EE);
+        assert_eq!(m.pc, 0x345);
+        assert!(m.stack.is_empty());
+    }
+
+    #[test]
+    fn i0_clears_the_vip_hires_screen_with_0230() {
+        let mut m = Machine::chip8();
+        m.screen.set_resolution(VIP_HIRES);
+        m.screen.flip(0, 63, 1);
+        m.exec(0x0230);
+        assert_eq!(m.pixels_on(), 0);
+
+        let mut m = Machine::chip8();
+        assert!(m.run(0x0230).is_err());
+    }
+
+    #[test]
+    fn i0_scrolls_on_superchip() {
+        let mut m = Machine::new(Platform::SuperChip);
+        m.screen.flip(10, 10, 1);
+        m.exec(0x00C3);
+        assert!(m.screen.is_on(10, 13));
+        m.exec(0x00FB);
+        assert!(m.screen.is_on(14, 13));
+        m.exec(0x00FC);
+        assert!(m.screen.is_on(10, 13));
+        assert_eq!(m.pixels_on(), 1);
+
+        assert!(Machine::chip8().run(0x00C3).is_err());
+        assert!(Machine::chip8().run(0x00FB).is_err());
+    }
+
+    #[test]
+    fn i0_scrolls_up_on_xochip() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.screen.flip(10, 10, 1);
+        m.exec(0x00D2);
+        assert!(m.screen.is_on(10, 8));
+        assert!(Machine::new(Platform::SuperChip).run(0x00D2).is_err());
+    }
+
+    #[test]
+    fn i0_switches_resolution_and_exits() {
+        let mut m = Machine::new(Platform::SuperChip);
+        m.exec(0x00FF);
+        assert_eq!(m.screen.resolution(), HIRES);
+        m.exec(0x00FE);
+        assert_eq!(m.screen.resolution(), LORES);
+        m.exec(0x00FD);
+        assert!(m.exited);
+        assert!(Machine::chip8().run(0x00FF).is_err());
+    }
+
+    #[test]
+    fn i0_rejects_machine_code_routines() {
+        assert_eq!(
+            Machine::chip8().run(0x0123),
+            Err(EmulatorError::UnknownOpcode {
+                pc: 0x200,
+                instruction: 0x0123
+            })
         );
     }
 
-    memory.write_adress(NNN);
-}
+    // 1NNN 2NNN
 
-pub fn iB(instruction: u16, pc: &mut u16, memory: &mut Memory) {
-    let NNN = instruction & 0x0FFF;
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn i1_jumps() {
+        let mut m = Machine::chip8();
+        m.exec(0x1ABC);
+        assert_eq!(m.pc, 0xABC);
+    }
 
-    if CB_B_NN == CB::OLD {
-        // 0xBNNN jump to 0x0NNN + V0
-        if DEBUG {
-            println!(
-                "0x{:03X} | 0x{:04X} | Jumping to 0x{:03X} + V0",
-                *pc - 2,
-                instruction,
-                NNN
-            );
+    #[test]
+    fn i2_calls_a_subroutine() {
+        let mut m = Machine::chip8();
+        m.exec(0x2ABC);
+        assert_eq!(m.pc, 0xABC);
+        assert_eq!(m.stack.adresses(), [0x202]);
+    }
+
+    #[test]
+    fn the_stack_errors_on_overflow_and_underflow() {
+        let mut m = Machine::chip8();
+        assert_eq!(
+            m.run(0x00EE),
+            Err(EmulatorError::StackUnderflow { pc: 0x200 })
+        );
+        m.pc = 0x300;
+        for _ in 0..16 {
+            m.exec(0x2300);
         }
+        assert_eq!(
+            m.run(0x2300),
+            Err(EmulatorError::StackOverflow { pc: 0x300 })
+        );
+        assert_eq!(m.stack.len(), 16);
+    }
 
-        let V0 = memory.read_register(0);
+    #[test]
+    fn the_errors_at_the_end_of_the_memory_wrap_the_pc() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.pc = 0xFFFE;
+        assert_eq!(
+            m.run(0x00EE),
+            Err(EmulatorError::StackUnderflow { pc: 0xFFFE })
+        );
+        m.pc = 0xFFFE;
+        m.exec(0xF00A);
+        assert_eq!(m.pc, 0xFFFE);
+    }
 
-        *pc = NNN + V0 as u16;
-    } else if CB_B_NN == CB::NEW {
-        // 0xBXNN jump to 0xXNN + VX
-        if DEBUG {
-            println!(
-                "0x{:03X} | 0x{:04X} | Jumping to 0x{:03X} + V{:01X}",
-                *pc - 2,
-                instruction,
-                NNN,
-                X
-            );
+    #[test]
+    fn the_cosmac_vip_stack_has_12_levels() {
+        let mut m = Machine::chip8();
+        m.options.quirks = Quirks::COSMAC_VIP;
+        m.stack = Stack::new(m.options.quirks.stack_depth as usize);
+        m.pc = 0x300;
+        for _ in 0..12 {
+            m.exec(0x2300);
         }
+        assert!(m.run(0x2300).is_err());
+        assert_eq!(m.stack.adresses(), [0x302; 12]);
+    }
 
-        let VX = memory.read_register(X);
-
-        *pc = NNN + VX as u16;
+    // Skips
+
+    #[test]
+    fn i34_skips_on_byte_comparison() {
+        let mut m = Machine::chip8();
+        m.set_v(3, 0x12);
+        m.exec(0x3312);
+        assert_eq!(m.pc, 0x204);
+        m.exec(0x3313);
+        assert_eq!(m.pc, 0x206);
+        m.exec(0x4313);
+        assert_eq!(m.pc, 0x20A);
+        m.exec(0x4312);
+        assert_eq!(m.pc, 0x20C);
     }
-}
 
-pub fn iC(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let NN = (instruction & 0x00FF) as usize;
+    #[test]
+    fn i59_skips_on_register_comparison() {
+        let mut m = Machine::chip8();
+        m.set_v(1, 7);
+        m.set_v(2, 7);
+        m.exec(0x5120);
+        assert_eq!(m.pc, 0x204);
+        m.exec(0x9120);
+        assert_eq!(m.pc, 0x206);
+        m.set_v(2, 8);
+        m.exec(0x9120);
+        assert_eq!(m.pc, 0x20A);
+        assert!(Machine::chip8().run(0x5121).is_err());
+        assert!(Machine::chip8().run(0x9121).is_err());
+    }
 
-    if DEBUG {
-        println!("0x{:03X} | 0x{:04X} | Setting V{:01X} to random number and binary-AND's it with 0x{:02X}", pc-2, instruction, X, NN);
+    #[test]
+    fn skips_jump_over_the_4_bytes_of_f000_on_xochip() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.memory.write(0x202, 0xF0);
+        m.memory.write(0x203, 0x00);
+        m.exec(0x3000);
+        assert_eq!(m.pc, 0x206);
     }
 
-    let mut rng = rand::thread_rng();
-    let random: u8 = rng.gen();
+    #[test]
+    fn i5_23_saves_and_loads_register_ranges() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.memory.write_adress(0x400);
+        for x in 0..4 {
+            m.set_v(x, x as u8 + 10);
+        }
+        m.exec(0x5132);
+        assert_eq!(
+            [0x400, 0x401, 0x402].map(|a| m.memory.read(a)),
+            [11, 12, 13]
+        );
+        m.exec(0x5312); // reverse order
+        assert_eq!(
+            [0x400, 0x401, 0x402].map(|a| m.memory.read(a)),
+            [13, 12, 11]
+        );
+        assert_eq!(m.memory.read_adress(), 0x400);
 
-    memory.write_register(X, random & NN as u8);
-}
+        m.memory.write(0x400, 42);
+        m.exec(0x5003);
+        assert_eq!(m.v(0), 42);
+        assert!(Machine::chip8().run(0x5132).is_err());
+    }
 
-pub fn iD(
-    memory: &mut Memory,
-    pc: u16,
-    instruction: u16,
-    screen: &mut Screen,
-    canvas: &mut WindowCanvas,
-) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
-    let Y = ((instruction & 0x00F0) >> 4) as usize;
-    let N = instruction & 0x000F;
+    // 6XNN 7XNN
 
-    let VX = memory.read_register(X);
-    let VY = memory.read_register(Y);
+    #[test]
+    fn i6_sets_a_register() {
+        let mut m = Machine::chip8();
+        m.exec(0x6A42);
+        assert_eq!(m.v(0xA), 0x42);
+    }
 
-    if DEBUG {
-        println!("0x{:03X} | 0x{:04X} | Displaying sprite at (V{:01X}, V{:01X}) = ({VX}, {VY}) with width 8 and height {:01X}", pc-2, instruction, X, Y, N);
+    #[test]
+    fn i7_adds_without_carry() {
+        let mut m = Machine::chip8();
+        m.set_v(2, 0xFF);
+        m.set_v(0xF, 5);
+        m.exec(0x7202);
+        assert_eq!(m.v(2), 1);
+        assert_eq!(m.v(0xF), 5);
     }
 
-    let mut cX = VX % 64; // coord X
-    let mut cY = VY % 32; // coord Y
-    let ccX = cX;
-    memory.write_register(0xF, 0);
+    // 8XYN
 
-    'rows: for i in 0..N {
-        let row = memory.read(memory.read_adress() + i);
-        'columns: for j in 0..8 {
-            let pixel = (row >> (7 - j)) & 0x1;
-            if pixel == 1 {
-                if screen.is_on(cX, cY) {
-                    memory.write_register(0xF, 1);
-                    screen.set(cX, cY, false);
-                } else {
-                    screen.set(cX, cY, true);
-                }
-            }
+    #[test]
+    fn i8_0_copies_a_register() {
+        let mut m = Machine::chip8();
+        m.set_v(4, 9);
+        m.exec(0x8140);
+        assert_eq!(m.v(1), 9);
+    }
 
-            let new_cX = cX as usize + 1;
-            if new_cX == 64 {
-                break 'columns;
-            } else {
-                cX = new_cX as u8;
-            }
-        }
-        cX = ccX;
-        let new_cY = cY as usize + 1;
-        if new_cY == 32 {
-            break 'rows;
-        } else {
-            cY = new_cY as u8;
+    #[test]
+    fn i8_123_logic_operations() {
+        let mut m = Machine::chip8();
+        for (instruction, result) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+            m.set_v(0, 0b1100);
+            m.set_v(1, 0b1010);
+            m.exec(instruction);
+            assert_eq!(m.v(0), result, "{instruction:04X}");
         }
     }
 
-    display::display(canvas, screen).expect("Error while displaying");
-}
-
-pub fn iE(instruction: u16, pc: &mut u16, memory: &mut Memory, keys_state: &KeysState) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn i8_457_add_with_carry() {
+        let mut m = Machine::chip8();
+        m.set_v(0, 200);
+        m.set_v(1, 100);
+        m.exec(0x8014);
+        assert_eq!((m.v(0), m.v(0xF)), (44, 1));
+        m.exec(0x8014);
+        assert_eq!((m.v(0), m.v(0xF)), (144, 0));
+    }
 
-    let VX = memory.read_register(X);
+    #[test]
+    fn i8_457_subtract_with_borrow() {
+        let mut m = Machine::chip8();
+        m.set_v(0, 10);
+        m.set_v(1, 3);
+        m.exec(0x8015);
+        assert_eq!((m.v(0), m.v(0xF)), (7, 1));
+        m.exec(0x8105); // 3 - 7
+        assert_eq!((m.v(1), m.v(0xF)), (252, 0));
+
+        m.set_v(0, 3);
+        m.set_v(1, 10);
+        m.exec(0x8017); // 10 - 3
+        assert_eq!((m.v(0), m.v(0xF)), (7, 1));
+        m.exec(0x8107); // 7 - 10
+        assert_eq!((m.v(1), m.v(0xF)), (253, 0));
+    }
 
-    let is_key_pressed_VX = keys_state.read_state(VX);
+    #[test]
+    fn i8_6e_shifts() {
+        let mut m = Machine::chip8();
+        m.set_v(0, 0b1000_0011);
+        m.exec(0x8016);
+        assert_eq!((m.v(0), m.v(0xF)), (0b0100_0001, 1));
+        m.exec(0x801E);
+        assert_eq!((m.v(0), m.v(0xF)), (0b1000_0010, 0));
+        m.exec(0x801E);
+        assert_eq!((m.v(0), m.v(0xF)), (0b0000_0100, 1));
+
+        // COSMAC VIP : VY is shifted into VX
+        m.options.quirks.shifting = false;
+        m.set_v(1, 0b0000_0110);
+        m.exec(0x8016);
+        assert_eq!(m.v(0), 0b0000_0011);
+        m.exec(0x801E);
+        assert_eq!(m.v(0), 0b0000_1100);
+    }
 
-    if instruction & 0x00FF == 0x009E {
-        if is_key_pressed_VX {
-            *pc += 2;
+    #[test]
+    fn i8_123_resets_vf_with_the_vf_reset_quirk() {
+        let mut m = Machine::chip8();
+        for instruction in [0x8011, 0x8012, 0x8013] {
+            m.set_v(0xF, 7);
+            m.exec(instruction);
+            assert_eq!(m.v(0xF), 7, "{instruction:04X}");
         }
-        if DEBUG && is_key_pressed_VX {
-            println!("0x{:03X} | 0x{:04X} | Skipping next instruction because the key with the value of V{:01X} ({:02X}) is pressed", *pc-2, instruction, X, VX);
-        } else if DEBUG {
-            println!("0x{:03X} | 0x{:04X} | Not skipping next instruction because the key with the value of V{:01X} ({:02X}) is not pressed", *pc-2, instruction, X, VX);
+        m.options.quirks.vf_reset = true;
+        for instruction in [0x8011, 0x8012, 0x8013] {
+            m.set_v(0xF, 7);
+            m.exec(instruction);
+            assert_eq!(m.v(0xF), 0, "{instruction:04X}");
         }
-    } else if instruction & 0x00FF == 0x00A1 {
-        if !is_key_pressed_VX {
-            *pc += 2;
+    }
+
+    #[test]
+    fn i8_457_subtraction_wraps() {
+        let mut m = Machine::chip8();
+        for (instruction, vx, vy, result, flag) in [
+            (0x8015, 0, 1, 0xFF, 0),
+            (0x8015, 0xFF, 0, 0xFF, 1),
+            (0x8015, 0x80, 0x80, 0, 1),
+            (0x8015, 0x01, 0xFF, 0x02, 0),
+            (0x8017, 1, 0, 0xFF, 0),
+            (0x8017, 0, 0xFF, 0xFF, 1),
+            (0x8017, 0xFF, 0x01, 0x02, 0),
+        ] {
+            m.set_v(0, vx);
+            m.set_v(1, vy);
+            m.exec(instruction);
+            assert_eq!(
+                (m.v(0), m.v(0xF)),
+                (result, flag),
+                "{instruction:04X} with V0 = {vx}, V1 = {vy}"
+            );
         }
-        if DEBUG && !is_key_pressed_VX {
-            println!("0x{:03X} | 0x{:04X} | Skipping next instruction because the key with the value of V{:01X} ({:02X}) is not pressed", *pc-2, instruction, X, VX);
-        } else if DEBUG {
-            println!("0x{:03X} | 0x{:04X} | Not skipping next instruction because the key with the value of V{:01X} ({:02X}) is pressed", *pc-2, instruction, X, VX);
+    }
+
+    // VF is written after VX, so when VF is VX it holds the flag
+    #[test]
+    fn i8_flags_overwrite_vf_as_operand() {
+        for (instruction, vf, vy, flag) in [
+            (0x8F14, 0xFF, 0x01, 1), // carry
+            (0x8F14, 0x01, 0x01, 0),
+            (0x8F15, 0x00, 0x01, 0), // borrow
+            (0x8F15, 0x05, 0x01, 1),
+            (0x8F17, 0x05, 0x01, 0),
+            (0x8F17, 0x01, 0x05, 1),
+            (0x8F06, 0x03, 0x00, 1), // bit shifted out
+            (0x8F06, 0x02, 0x00, 0),
+            (0x8F0E, 0x80, 0x00, 1),
+            (0x8F0E, 0x40, 0x00, 0),
+        ] {
+            let mut m = Machine::chip8();
+            m.set_v(0xF, vf);
+            m.set_v(1, vy);
+            m.exec(instruction);
+            assert_eq!(
+                m.v(0xF),
+                flag,
+                "{instruction:04X} with VF = {vf}, V1 = {vy}"
+            );
         }
-    } else {
-        panic!(
-            "{}",
-            NonUsedInstructionError {
-                pc: *pc - 2,
-                instruction
-            }
-        )
     }
-}
 
-pub fn iF_07(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn i8_flags_use_vy_as_operand() {
+        // VY is read before VF is written when VY is VF
+        let mut m = Machine::chip8();
+        m.set_v(0, 10);
+        m.set_v(0xF, 3);
+        m.exec(0x80F5);
+        assert_eq!((m.v(0), m.v(0xF)), (7, 1));
+
+        m.options.quirks.shifting = false;
+        m.set_v(0xF, 0x81);
+        m.exec(0x80FE);
+        assert_eq!((m.v(0), m.v(0xF)), (0x02, 1));
+    }
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Setting V{:01X} to the value of the delay timer",
-            pc - 2,
-            instruction,
-            X
-        );
+    // ANNN BNNN CXNN
+
+    #[test]
+    fn ia_sets_i() {
+        let mut m = Machine::chip8();
+        m.exec(0xA123);
+        assert_eq!(m.memory.read_adress(), 0x123);
     }
 
-    let timer_val = memory.read_delay_timer();
-    memory.write_register(X, timer_val);
-}
+    #[test]
+    fn ib_jumps_with_offset() {
+        let mut m = Machine::chip8();
+        m.set_v(0, 0x10);
+        m.set_v(3, 0x20);
+        m.exec(0xB300); // 0x300 + V3
+        assert_eq!(m.pc, 0x320);
+
+        m.options.quirks.jumping = false;
+        m.exec(0xB300); // 0x300 + V0
+        assert_eq!(m.pc, 0x310);
+    }
 
-pub fn iF_0A(instruction: u16, pc: &mut u16, memory: &mut Memory, keys_state: &KeysState) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn ic_masks_the_random_number() {
+        let mut m = Machine::chip8();
+        for _ in 0..20 {
+            m.exec(0xC50F);
+            assert_eq!(m.v(5) & 0xF0, 0);
+        }
+        m.exec(0xC500);
+        assert_eq!(m.v(5), 0);
+    }
 
-    if DEBUG {
-        println!("0x{:03X} | 0x{:04X} | Waiting for a key press, storing the value of the key in V{:01X}", *pc-2, instruction, X);
+    // DXYN
+
+    #[test]
+    fn id_draws_with_collision() {
+        let mut m = Machine::chip8();
+        m.memory.write(0x300, 0b1100_0000);
+        m.memory.write(0x301, 0b0100_0000);
+        m.memory.write_adress(0x300);
+        m.set_v(0, 10);
+        m.set_v(1, 5);
+        m.exec(0xD012);
+        assert!(m.screen.is_on(10, 5) && m.screen.is_on(11, 5) && m.screen.is_on(11, 6));
+        assert_eq!((m.pixels_on(), m.v(0xF)), (3, 0));
+        m.exec(0xD012);
+        assert_eq!((m.pixels_on(), m.v(0xF)), (0, 1));
     }
 
-    match keys_state.is_key_pressed() {
-        Some(key_pressed) => memory.write_register(X, key_pressed),
-        None => *pc -= 2,
+    #[test]
+    fn id_wraps_the_position_and_clips_the_sprite() {
+        let mut m = Machine::chip8();
+        m.memory.write(0x300, 0xFF);
+        m.memory.write_adress(0x300);
+        m.set_v(0, 64 + 60); // x = 60
+        m.set_v(1, 31);
+        m.exec(0xD011);
+        assert_eq!(m.pixels_on(), 4);
+        assert!(m.screen.is_on(63, 31));
     }
-}
 
-pub fn iF_1518(instruction: u16, pc: &mut u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn id_wraps_the_sprite_without_clipping() {
+        let mut m = Machine::chip8();
+        m.options.quirks.clipping = false;
+        m.memory.write(0x300, 0xFF);
+        m.memory.write(0x301, 0xFF);
+        m.memory.write_adress(0x300);
+        m.set_v(0, 60);
+        m.set_v(1, 31);
+        m.exec(0xD012);
+        assert_eq!(m.pixels_on(), 16);
+        assert!(m.screen.is_on(63, 31) && m.screen.is_on(0, 31));
+        assert!(m.screen.is_on(63, 0) && m.screen.is_on(3, 0));
+    }
 
-    if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Setting the sound timer to V{:01X}",
-            *pc - 2,
-            instruction,
-            X
-        );
+    #[test]
+    fn id_draws_16x16_sprites_on_superchip() {
+        let mut m = Machine::new(Platform::SuperChip);
+        for i in 0..32 {
+            m.memory.write(0x300 + i, 0xFF);
+        }
+        m.memory.write_adress(0x300);
+        m.exec(0x00FF);
+        m.exec(0xD000);
+        assert_eq!(m.pixels_on(), 256);
     }
 
-    let VX = memory.read_register(X);
+    #[test]
+    fn id_draws_each_selected_plane_on_xochip() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.memory.write(0x300, 0x80); // plane 1
+        m.memory.write(0x301, 0x40); // plane 2
+        m.memory.write_adress(0x300);
+        m.exec(0xF301);
+        m.exec(0xD001);
+        assert_eq!(m.screen.pixel(0, 0), 1);
+        assert_eq!(m.screen.pixel(1, 0), 2);
+    }
 
-    if instruction & 0x00FF == 0x0015 {
-        memory.write_delay_timer(VX);
-    } else {
-        memory.write_sound_timer(VX);
+    // EX9E EXA1
+
+    #[test]
+    fn ie_skips_on_keys() {
+        let mut m = Machine::chip8();
+        m.set_v(0, 0xA);
+        m.exec(0xE09E);
+        assert_eq!(m.pc, 0x202);
+        m.exec(0xE0A1);
+        assert_eq!(m.pc, 0x206);
+
+        m.keys_state.update_state(0xA, true);
+        m.exec(0xE09E);
+        assert_eq!(m.pc, 0x20A);
+        m.exec(0xE0A1);
+        assert_eq!(m.pc, 0x20C);
+
+        m.set_v(1, 0x1F);
+        m.keys_state.update_state(0xF, true);
+        m.exec(0xE19E);
+        assert_eq!(m.pc, 0x210);
+    }
+
+    // FXNN
+
+    #[test]
+    fn if_000_sets_a_16_bit_i() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.memory.write(0x202, 0xBE);
+        m.memory.write(0x203, 0xEF);
+        m.exec(0xF000);
+        assert_eq!(m.memory.read_adress(), 0xBEEF);
+        assert_eq!(m.pc, 0x204);
+        assert!(Machine::chip8().run(0xF000).is_err());
     }
-}
 
-pub fn iF_1E(instruction: u16, pc: &mut u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn if_01_selects_planes() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.exec(0xF201);
+        assert_eq!(m.screen.selected_planes(), 2);
+    }
 
-    let VX = memory.read_register(X);
-    let new_I = memory.read_adress() as usize + VX as usize;
-    if CB_FX1E == CB::NEW && new_I > 0xFFF {
-        if DEBUG {
-            println!(
-                "0x{:03X} | 0x{:04X} | Adding V{:01X} to I with carry flag",
-                *pc - 2,
-                instruction,
-                X
-            );
+    #[test]
+    fn if_002_loads_the_audio_pattern() {
+        let mut m = Machine::new(Platform::XoChip);
+        for i in 0..16 {
+            m.memory.write(0x300 + i, i as u8);
         }
-        memory.write_register(0xF, 1);
-    } else if DEBUG {
-        println!(
-            "0x{:03X} | 0x{:04X} | Adding V{:01X} to I",
-            *pc - 2,
-            instruction,
-            X
+        m.memory.write_adress(0x300);
+        m.exec(0xF002);
+        assert_eq!(
+            m.memory.read_audio_pattern(),
+            core::array::from_fn(|i| i as u8)
         );
     }
-    memory.write_adress((new_I % 0x1000) as u16);
-}
 
-pub fn iF_29(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn if_07_reads_the_delay_timer() {
+        let mut m = Machine::chip8();
+        m.memory.write_delay_timer(33);
+        m.exec(0xF407);
+        assert_eq!(m.v(4), 33);
+    }
 
-    if DEBUG {
-        println!("0x{:03X} | 0x{:04X} | Setting I to the location of the sprite for the character in V{:01X}", pc-2, instruction, X);
+    #[test]
+    fn if_0a_returns_on_the_press_without_the_key_release_quirk() {
+        let mut m = Machine::chip8();
+        m.options.quirks.key_release = false;
+        m.exec(0xF30A);
+        assert_eq!(m.pc, 0x200);
+        m.keys_state.update_state(0x7, true);
+        m.exec(0xF30A);
+        assert_eq!((m.pc, m.v(3)), (0x202, 7));
     }
 
-    let char_0x = memory.read_register(X) & 0x0F;
-    memory.write_adress((char_0x as u16) * 5 + 50);
-}
+    #[test]
+    fn if_0a_ignores_the_keys_held_before_waiting() {
+        let mut m = Machine::chip8();
+        m.options.quirks.key_release = false;
+        m.keys_state.update_state(0x2, true);
+        m.exec(0xF30A);
+        m.exec(0xF30A);
+        assert_eq!(m.pc, 0x200);
+        // The first key pressed wins, not the lowest one
+        m.keys_state.update_state(0x9, true);
+        m.keys_state.update_state(0x1, true);
+        m.exec(0xF30A);
+        assert_eq!((m.pc, m.v(3)), (0x202, 9));
+    }
 
-pub fn iF_33(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn if_0a_waits_for_the_release_by_default() {
+        let mut m = Machine::chip8();
+        m.exec(0xF30A);
+        m.keys_state.update_state(0x7, true);
+        m.exec(0xF30A);
+        m.keys_state.update_state(0x4, true);
+        m.keys_state.update_state(0x4, false);
+        m.exec(0xF30A);
+        assert_eq!((m.pc, m.v(3)), (0x200, 0));
+        m.keys_state.update_state(0x7, false);
+        m.exec(0xF30A);
+        assert_eq!((m.pc, m.v(3)), (0x202, 7));
+    }
 
-    if DEBUG {
-        println!("0x{:03X} | 0x{:04X} | Storing the binary-coded decimal representation of V{:01X} at the addresses I, I+1, and I+2", pc-2, instruction, X);
+    #[test]
+    fn if_1518_set_the_timers() {
+        let mut m = Machine::chip8();
+        m.set_v(1, 60);
+        m.exec(0xF115);
+        m.set_v(1, 30);
+        m.exec(0xF118);
+        assert_eq!(m.memory.read_delay_timer(), 60);
+        assert_eq!(m.memory.read_sound_timer(), 30);
     }
 
-    let VX = memory.read_register(X);
-    let (digit_1, digit_2, digit_3) = (VX / 100, (VX / 10) % 10, VX % 10);
-    let I = memory.read_adress();
-    memory.write(I, digit_1);
-    memory.write(I + 1, digit_2);
-    memory.write(I + 2, digit_3);
-}
+    #[test]
+    fn if_1e_adds_to_i() {
+        let mut m = Machine::chip8();
+        m.memory.write_adress(0x300);
+        m.set_v(2, 0x10);
+        m.set_v(0xF, 1);
+        m.exec(0xF21E);
+        assert_eq!((m.memory.read_adress(), m.v(0xF)), (0x310, 0));
+
+        m.memory.write_adress(0xFFF);
+        m.exec(0xF21E);
+        assert_eq!((m.memory.read_adress(), m.v(0xF)), (0x00F, 1));
+
+        m.options.quirks.i_overflow = false;
+        m.set_v(0xF, 7);
+        m.exec(0xF21E);
+        assert_eq!((m.memory.read_adress(), m.v(0xF)), (0x01F, 7));
+    }
 
-pub fn iF_5565(instruction: u16, pc: u16, memory: &mut Memory) {
-    let X = ((instruction & 0x0F00) >> 8) as usize;
+    #[test]
+    fn if_1e_past_the_memory_stops_without_memory_wrap() {
+        let mut m = Machine::chip8();
+        m.options.quirks.memory_wrap = false;
+        m.memory.write_adress(0xFF8);
+        m.set_v(2, 0x10);
+        assert!(matches!(
+            m.run(0xF21E),
+            Err(EmulatorError::OutOfBounds {
+                pc: 0x200,
+                adress: 0x1008
+            })
+        ));
+        assert_eq!(m.memory.read_adress(), 0xFF8);
+    }
 
-    let I = memory.read_adress();
-    if DEBUG {
-        let (action, particle) = if instruction & 0x00FF == 0x0055 {
-            ("Storing", "to")
-        } else {
-            ("Loading", "from")
-        };
-        println!("0x{:03X} | 0x{:04X} | {action} V0 through V{:01X} {particle} memory starting at address I", pc-2, instruction, X);
+    #[test]
+    fn if_29_points_i_at_the_font() {
+        let mut m = Machine::chip8();
+        m.set_v(0, 0xA);
+        m.exec(0xF029);
+        assert_eq!(m.memory.read_adress(), FONT_ADRESS + 0xA * 5);
     }
-    for i in (0..NB_REGISTERS).take(X + 1) {
-        let iu16 = i as u16;
-        if instruction & 0x00FF == 0x0055 {
-            let Vi = memory.read_register(i);
-            if DEBUG_VERBOSE {
-                println!("               | Storing V{:01X} = 0x{:02X} ({Vi}) in memory at address {:03X}", i, Vi, I+i as u16);
-            }
-            memory.write(I + iu16, Vi);
-        } else {
-            /* instruction & 0x00FF == 0x0065 */
-            let future_Vi = memory.read(I + iu16);
-            if DEBUG_VERBOSE {
-                println!("               | Storing memory at address {:03X} = 0x{:02X} ({future_Vi}) in V{:01X}", I+i as u16, future_Vi, i);
-            }
-            memory.write_register(i, future_Vi);
+
+    #[test]
+    fn if_30_points_i_at_the_big_font() {
+        let mut m = Machine::new(Platform::SuperChip);
+        m.set_v(0, 9);
+        m.exec(0xF030);
+        assert_eq!(m.memory.read_adress(), BIG_FONT_ADRESS + 9 * 10);
+        assert!(Machine::chip8().run(0xF030).is_err());
+    }
+
+    #[test]
+    fn if_3a_sets_the_pitch() {
+        let mut m = Machine::new(Platform::XoChip);
+        m.set_v(0, 112);
+        m.exec(0xF03A);
+        assert_eq!(m.memory.read_pitch(), 112);
+    }
+
+    #[test]
+    fn if_33_stores_bcd() {
+        let mut m = Machine::chip8();
+        m.set_v(6, 254);
+        m.memory.write_adress(0x300);
+        m.exec(0xF633);
+        assert_eq!([0x300, 0x301, 0x302].map(|a| m.memory.read(a)), [2, 5, 4]);
+    }
+
+    #[test]
+    fn if_5565_store_and_load_registers() {
+        let mut m = Machine::chip8();
+        m.memory.write_adress(0x300);
+        for x in 0..3 {
+            m.set_v(x, x as u8 + 1);
         }
+        m.exec(0xF255);
+        assert_eq!(
+            [0x300, 0x301, 0x302, 0x303].map(|a| m.memory.read(a)),
+            [1, 2, 3, 0]
+        );
+        assert_eq!(m.memory.read_adress(), 0x300);
+
+        m.memory.write(0x300, 9);
+        m.exec(0xF065);
+        assert_eq!((m.v(0), m.v(1)), (9, 2));
+
+        // CHIP-48 : I is incremented by X, COSMAC VIP : by X + 1
+        m.options.quirks.memory = MemoryIncrement::X;
+        m.exec(0xF255);
+        assert_eq!(m.memory.read_adress(), 0x302);
+        m.options.quirks.memory = MemoryIncrement::XPlusOne;
+        m.exec(0xF255);
+        assert_eq!(m.memory.read_adress(), 0x305);
+    }
+
+    #[test]
+    fn if_7585_store_and_load_rpl_flags() {
+        let mut m = Machine::new(Platform::SuperChip);
+        m.set_v(0, 4);
+        m.set_v(1, 5);
+        m.exec(0xF175);
+        m.set_v(0, 0);
+        m.set_v(1, 0);
+        m.exec(0xF085);
+        assert_eq!((m.v(0), m.v(1)), (4, 0));
+        assert!(Machine::chip8().run(0xF175).is_err());
     }
-    if CB_FX_5 == CB::OLD {
-        memory.write_adress(I + (X as u16) + 1);
+
+    // Memory bounds
+
+    #[test]
+    fn the_adresses_past_the_memory_wrap_around() {
+        let mut m = Machine::chip8();
+        m.memory.write_adress(0xFFE);
+        m.set_v(0, 123);
+        m.exec(0xF033);
+        assert_eq!(
+            [0xFFE, 0xFFF, 0x000].map(|adress| m.memory.read(adress)),
+            [1, 2, 3]
+        );
+        m.memory.write_adress(0xFFF);
+        m.exec(0xF165);
+        assert_eq!((m.v(0), m.v(1)), (2, 3));
+
+        let mut m = Machine::new(Platform::XoChip);
+        m.memory.write_adress(0xFFFF);
+        m.set_v(1, 0xAB);
+        m.exec(0xF155);
+        assert_eq!(m.memory.read(0x0000), 0xAB);
+    }
+
+    #[test]
+    fn the_pc_wraps_around_the_memory() {
+        let mut m = Machine::chip8();
+        m.pc = 0x1000;
+        m.exec(0x6007);
+        assert_eq!((m.pc, m.v(0)), (0x002, 7));
+    }
+
+    #[test]
+    fn the_adresses_past_the_memory_stop_without_memory_wrap() {
+        let mut m = Machine::new(Platform::SuperChip);
+        assert!(!m.options.quirks.memory_wrap);
+        let out_of_bounds = |pc| Err(EmulatorError::OutOfBounds { pc, adress: 0x1000 });
+
+        // The last byte accessed is the last one of the memory
+        m.memory.write_adress(0xFFD);
+        m.exec(0xF033);
+        m.memory.write_adress(0xFFE);
+        assert_eq!(m.run(0xF033), out_of_bounds(0x202));
+
+        m.memory.write_adress(0xFF8);
+        m.exec(0xF755);
+        assert_eq!(m.run(0xF855), out_of_bounds(0x206));
+
+        m.memory.write_adress(0xFFC);
+        m.exec(0xD014);
+        assert_eq!(m.run(0xD015), out_of_bounds(0x20A));
+
+        m.pc = 0xFFF;
+        assert_eq!(m.run(0x00E0), out_of_bounds(0xFFF));
     }
 }
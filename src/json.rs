@@ -0,0 +1,268 @@
+// A small JSON reader, enough for the rom database and the configuration files
+
+/// A parsed JSON value. The members of an object keep their order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a whole JSON document, the errors give the line and column.
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected data after the value"));
+        }
+        Ok(value)
+    }
+
+    /// The member `key` of an object, `None` for the other values.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(bool) => Some(*bool),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    pub fn members(&self) -> &[(String, Json)] {
+        match self {
+            Json::Object(members) => members,
+            _ => &[],
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize, // in bytes
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        format!("{line}:{column}: {message}")
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{expected}'")));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                for (word, value) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            self.expect(':')?;
+            members.push((name, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1; // opening quote
+        let mut string = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += escaped.len_utf8();
+                    string.push(match escaped {
+                        '"' | '\\' | '/' => escaped,
+                        'b' => '\u{8}',
+                        'f' => '\u{C}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let hex = self.text.get(self.pos..self.pos + 4).unwrap_or("");
+                            let code = u32::from_str_radix(hex, 16)
+                                .map_err(|_| self.error("invalid unicode escape"))?;
+                            self.pos += 4;
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                _ => string.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+        {
+            self.pos += 1;
+        }
+        self.text[start..self.pos]
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let json = Json::parse(r#"{"a": [1, -2.5e1, true, null], "b": {"c": "d\"é"}}"#).unwrap();
+        assert_eq!(
+            json.get("a").unwrap().as_array(),
+            [
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]
+        );
+        assert_eq!(
+            json.get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Json::as_str),
+            Some("d\"é")
+        );
+    }
+
+    #[test]
+    fn errors_give_the_position() {
+        assert_eq!(
+            Json::parse("{\n  \"a\" 1}"),
+            Err("2:7: expected ':'".to_string())
+        );
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("1 2").is_err());
+        assert_eq!(
+            Json::parse(r#"["\é"]"#),
+            Err("1:5: invalid escape".to_string())
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::{env, fs, path::PathBuf};
+
+use crate::json::Json;
+use crate::rom_database::GameKeys;
+
+// The physical keys of the CHIP-8 keys 0 to F, laid out like the keypad of the COSMAC VIP :
+// 1 2 3 C
+// 4 5 6 D
+// 7 8 9 E
+// A 0 B F
+const LAYOUTS: [(&str, [&str; 16]); 2] = [
+    (
+        "qwerty",
+        [
+            "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+        ],
+    ),
+    (
+        "azerty",
+        [
+            "X", "1", "2", "3", "A", "Z", "E", "Q", "S", "D", "W", "C", "4", "R", "F", "V",
+        ],
+    ),
+];
+
+pub const DEFAULT_LAYOUT: &str = "azerty";
+
+// The buttons of a controller for the keys most games move and shoot with
+const DEFAULT_BUTTONS: [(&str, u8); 5] = [
+    ("dpup", 2),
+    ("dpdown", 8),
+    ("dpleft", 4),
+    ("dpright", 6),
+    ("a", 5),
+];
+
+/// Number of controllers that can play at the same time, the next ones are not used.
+pub const PLAYERS: usize = 2;
+
+/// Which CHIP-8 key each physical key plays, a CHIP-8 key can have several physical keys.
+/// The physical keys are named like SDL does (`A`, `1`, `Up`, `Space`, `Left Shift`, `Keypad 1`...),
+/// without caring about the case. The buttons of the controllers too (`a`, `dpup`, `leftshoulder`...).
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct KeyMap {
+    keys: HashMap<String, u8>,
+    buttons: [HashMap<String, u8>; PLAYERS], // of the controller of each player
+}
+
+impl KeyMap {
+    pub fn layout(name: &str) -> Result<KeyMap, String> {
+        let (_, physical_keys) = LAYOUTS
+            .iter()
+            .find(|(layout, _)| layout.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                format!("Unknown keyboard layout: {name} (expected qwerty or azerty)")
+            })?;
+        let mut keymap = KeyMap::default();
+        for (key, physical_key) in physical_keys.iter().enumerate() {
+            keymap.bind(physical_key, key as u8);
+        }
+        for player in 0..PLAYERS {
+            for (button, key) in DEFAULT_BUTTONS {
+                keymap.bind_button(player, button, key);
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// The CHIP-8 key played by this physical key.
+    pub fn key(&self, physical_key: &str) -> Option<u8> {
+        self.keys.get(&physical_key.to_ascii_lowercase()).copied()
+    }
+
+    /// The physical keys of a CHIP-8 key, sorted.
+    pub fn physical_keys(&self, key: u8) -> Vec<&str> {
+        let mut physical_keys: Vec<&str> = self
+            .keys
+            .iter()
+            .filter(|(_, k)| **k == key)
+            .map(|(physical_key, _)| physical_key.as_str())
+            .collect();
+        physical_keys.sort();
+        physical_keys
+    }
+
+    /// Adds a physical key to a CHIP-8 key, the physical key stops playing its previous key.
+    pub fn bind(&mut self, physical_key: &str, key: u8) {
+        self.keys.insert(physical_key.to_ascii_lowercase(), key);
+    }
+
+    /// Removes all the physical keys of a CHIP-8 key.
+    pub fn unbind(&mut self, key: u8) {
+        self.keys.retain(|_, k| *k != key);
+    }
+
+    /// The CHIP-8 key played by a button of the controller of `player` (from 0).
+    pub fn button(&self, player: usize, button: &str) -> Option<u8> {
+        self.buttons
+            .get(player)?
+            .get(&button.to_ascii_lowercase())
+            .copied()
+    }
+
+    /// Adds a button to a CHIP-8 key for the controller of `player` (from 0).
+    pub fn bind_button(&mut self, player: usize, button: &str, key: u8) {
+        self.buttons[player].insert(button.to_ascii_lowercase(), key);
+    }
+
+    /// The CHIP-8 keys of all the buttons of the controller of `player` (from 0).
+    pub fn button_keys(&self, player: usize) -> Vec<u8> {
+        self.buttons
+            .get(player)
+            .map_or(Vec::new(), |buttons| buttons.values().copied().collect())
+    }
+
+    /// Removes all the buttons of a CHIP-8 key for the controller of `player` (from 0).
+    pub fn unbind_button(&mut self, player: usize, key: u8) {
+        self.buttons[player].retain(|_, k| *k != key);
+    }
+
+    /// The keys of the game found in the rom database are played with the arrows, Space and
+    /// Left Shift, and with the directions, A and B of the controllers.
+    /// The second player uses the keys of the first one if the database gives none.
+    pub fn bind_game_keys(&mut self, game_keys: &[GameKeys; PLAYERS]) {
+        for (player, keys) in game_keys.iter().enumerate() {
+            let keys = if *keys == GameKeys::default() {
+                &game_keys[0]
+            } else {
+                keys
+            };
+            for (physical_key, button, key) in [
+                ("Up", "dpup", keys.up),
+                ("Down", "dpdown", keys.down),
+                ("Left", "dpleft", keys.left),
+                ("Right", "dpright", keys.right),
+                ("Space", "a", keys.a),
+                ("Left Shift", "b", keys.b),
+            ] {
+                if let Some(key) = key {
+                    if player == 0 {
+                        self.bind(physical_key, key);
+                    }
+                    self.bind_button(player, button, key);
+                }
+            }
+        }
+    }
+
+    // {"keys": {"5": ["W", "Up"], "A": "Space"}, "controllers": [{"5": ["a", "x"]}, {"C": "dpup"}]}
+    // The CHIP-8 keys given lose their other keys or buttons
+    fn apply(&mut self, config: &Json) -> Result<(), String> {
+        if let Some(keys) = config.get("keys") {
+            for (key, physical_keys) in bindings(keys)? {
+                self.unbind(key);
+                for physical_key in physical_keys {
+                    self.bind(physical_key, key);
+                }
+            }
+        }
+        let controllers = config.get("controllers").map_or(&[][..], Json::as_array);
+        for (player, buttons) in controllers.iter().enumerate().take(PLAYERS) {
+            for (key, buttons) in bindings(buttons)? {
+                self.unbind_button(player, key);
+                for button in buttons {
+                    self.bind_button(player, button, key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// {"5": ["W", "Up"], "A": "Space"}
+fn bindings(config: &Json) -> Result<Vec<(u8, Vec<&str>)>, String> {
+    let mut bindings = Vec::new();
+    for (key, names) in config.members() {
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(|| format!("Invalid CHIP-8 key: {key} (expected 0 to F)"))?;
+        let names = match names {
+            Json::String(name) => vec![name.as_str()],
+            Json::Array(names) => names
+                .iter()
+                .map(|name| {
+                    name.as_str().ok_or_else(|| {
+                        format!("The keys of {key:X} must be strings like \"A\" or \"Up\"")
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(format!("The keys of {key:X} must be a string or a list")),
+        };
+        bindings.push((key, names));
+    }
+    Ok(bindings)
+}
+
+/// Builds the key map of a rom from the text of the config file (see README.md).
+/// The layout is the one given (command line), else the one of the section of the rom in "roms"
+/// (found by SHA-1 or file name), else the one of the file, else AZERTY. The keys of the rom
+/// database are added, then the keys and buttons of the file, then the ones of the section of the rom.
+pub fn load(
+    config: &str,
+    layout: Option<&str>,
+    game_keys: &[GameKeys; PLAYERS],
+    rom_sha1: &str,
+    rom_name: &str,
+) -> Result<KeyMap, String> {
+    let config = if config.trim().is_empty() {
+        Json::Object(Vec::new())
+    } else {
+        Json::parse(config)?
+    };
+    let rom = config.get("roms").and_then(|roms| {
+        roms.members()
+            .iter()
+            .find(|(rom, _)| rom.eq_ignore_ascii_case(rom_sha1) || rom == rom_name)
+            .map(|(_, rom)| rom)
+    });
+
+    let layout = match (layout, rom.map(layout_of).transpose()?.flatten()) {
+        (Some(layout), _) | (None, Some(layout)) => layout,
+        (None, None) => layout_of(&config)?.unwrap_or(DEFAULT_LAYOUT),
+    };
+
+    let mut keymap = KeyMap::layout(layout)?;
+    keymap.bind_game_keys(game_keys);
+    keymap.apply(&config)?;
+    if let Some(rom) = rom {
+        keymap.apply(rom)?;
+    }
+    Ok(keymap)
+}
+
+fn layout_of(config: &Json) -> Result<Option<&str>, String> {
+    match config.get("layout") {
+        Some(layout) => layout
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| "The layout must be a string (qwerty or azerty)".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/chip/keys.json`, `~/.config/chip/keys.json` or `%APPDATA%\chip\keys.json`.
+pub fn config_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config.join("chip").join("keys.json"))
+}
+
+/// The text of the config file, empty if there is none.
+pub fn read_config(path: Option<PathBuf>) -> Result<String, String> {
+    match path.filter(|path| path.exists()) {
+        Some(path) => fs::read_to_string(&path)
+            .map_err(|err| format!("Could not read {} : {err}", path.display())),
+        None => Ok(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_map_the_keypad() {
+        let qwerty = KeyMap::layout("QWERTY").unwrap();
+        assert_eq!(
+            (qwerty.key("1"), qwerty.key("q"), qwerty.key("V")),
+            (Some(1), Some(4), Some(0xF))
+        );
+        let azerty = KeyMap::layout("azerty").unwrap();
+        assert_eq!(
+            (azerty.key("A"), azerty.key("W"), azerty.key("Up")),
+            (Some(4), Some(0xA), None)
+        );
+        assert!(KeyMap::layout("dvorak").is_err());
+    }
+
+    #[test]
+    fn the_config_adds_physical_keys_and_overrides_roms() {
+        let config = r#"{
+            "layout": "qwerty",
+            "keys": {"5": ["W", "Up"], "a": "Space"},
+            "roms": {
+                "0123abcd": {"keys": {"6": ["Right", "Keypad 6"]}},
+                "pong.ch8": {"layout": "azerty"}
+            }
+        }"#;
+        let game_keys = [
+            GameKeys {
+                up: Some(2),
+                right: Some(9),
+                ..GameKeys::default()
+            },
+            GameKeys::default(),
+        ];
+
+        let keymap = load(config, None, &game_keys, "0123ABCD", "other.ch8").unwrap();
+        assert_eq!(keymap.physical_keys(5), ["up", "w"]);
+        assert_eq!(keymap.physical_keys(0xA), ["space"]);
+        assert_eq!(keymap.physical_keys(6), ["keypad 6", "right"]);
+        assert_eq!(keymap.key("Z"), None);
+
+        // The layout of the rom, with the keys of the file
+        let keymap = load(config, None, &game_keys, "ffff", "pong.ch8").unwrap();
+        assert_eq!((keymap.key("A"), keymap.key("Q")), (Some(4), Some(7)));
+        assert_eq!((keymap.key("Up"), keymap.key("Right")), (Some(5), Some(9)));
+
+        // The layout of the command line wins
+        let keymap = load(config, Some("azerty"), &game_keys, "ffff", "other.ch8").unwrap();
+        assert_eq!((keymap.key("Q"), keymap.key("Up")), (Some(7), Some(5)));
+        assert_eq!(
+            load("", None, &game_keys, "", "").unwrap().key("Up"),
+            Some(2)
+        );
+
+        assert!(load(r#"{"keys": {"G": "A"}}"#, None, &game_keys, "", "").is_err());
+    }
+
+    #[test]
+    fn the_controllers_play_the_game_keys_of_each_player() {
+        let pong = [
+            GameKeys {
+                up: Some(1),
+                down: Some(4),
+                ..GameKeys::default()
+            },
+            GameKeys {
+                up: Some(0xC),
+                down: Some(0xD),
+                ..GameKeys::default()
+            },
+        ];
+        let keymap = load("", None, &pong, "", "").unwrap();
+        assert_eq!(
+            (keymap.button(0, "dpup"), keymap.button(0, "A")),
+            (Some(1), Some(5))
+        );
+        assert_eq!(
+            (keymap.button(1, "dpup"), keymap.button(1, "dpdown")),
+            (Some(0xC), Some(0xD))
+        );
+        assert_eq!(keymap.button(2, "dpup"), None);
+
+        // Without keys for the second player, both play the same keys
+        let tank = [pong[0], GameKeys::default()];
+        let keymap = load("", None, &tank, "", "").unwrap();
+        assert_eq!(keymap.button(1, "dpdown"), Some(4));
+
+        let config = r#"{"controllers": [{"5": ["x", "y"]}], "roms": {"pong.ch8": {"controllers": [{}, {"0": "start"}]}}}"#;
+        let keymap = load(config, None, &pong, "", "pong.ch8").unwrap();
+        assert_eq!(
+            (
+                keymap.button(0, "a"),
+                keymap.button(0, "x"),
+                keymap.button(0, "y")
+            ),
+            (None, Some(5), Some(5))
+        );
+        assert_eq!(
+            (keymap.button(1, "a"), keymap.button(1, "start")),
+            (Some(5), Some(0))
+        );
+    }
+}
@@ -0,0 +1,51 @@
+/// Where FX0A is in its wait for a key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum KeyWait {
+    Idle,
+    Press,        // waiting for a key to be pressed, the keys already held don't count
+    Pressed(u8),  // waiting for this key to be released
+    Released(u8), // pressed then released while FX0A was waiting
+}
+
+pub struct KeysState {
+    keys: [bool; 16],
+    wait: KeyWait,
+}
+
+impl KeysState {
+    pub fn new() -> Self {
+        KeysState {
+            keys: [false; 16],
+            wait: KeyWait::Idle,
+        }
+    }
+
+    pub fn read_state(&self, key: u8) -> bool {
+        self.keys[key as usize]
+    }
+
+    pub(crate) fn wait(&self) -> KeyWait {
+        self.wait
+    }
+
+    pub(crate) fn set_wait(&mut self, wait: KeyWait) {
+        self.wait = wait;
+    }
+
+    pub(crate) fn update_state(&mut self, key: u8, state: bool) {
+        let index = key as usize;
+        // The first key pressed wins, not the lowest one
+        if state && !self.keys[index] && self.wait == KeyWait::Press {
+            self.wait = KeyWait::Pressed(key);
+        } else if !state && self.keys[index] && self.wait == KeyWait::Pressed(key) {
+            self.wait = KeyWait::Released(key);
+        }
+        self.keys[index] = state;
+    }
+}
+
+impl Default for KeysState {
+    fn default() -> Self {
+        KeysState::new()
+    }
+}
@@ -1,47 +1,418 @@
-#![allow(clippy::upper_case_acronyms)]
-
-//pub const ROM_PATH: &str = "roms/test_opcode.ch8";
-//pub const ROM_PATH: &str = "roms/autre/PONG2";          // Problème d'affichage des points
-pub const ROM_PATH: &str = "roms/autre/INVADERS";
-//pub const ROM_PATH: &str = "roms/autre/TETRIS";
-//pub const ROM_PATH: &str = "roms/programs/Keypad Test [Hap, 2006].ch8";  // Affiche mal somehow ??
-
-pub const IPS: u64 = 700; // instructions per second
-
-pub const DEBUG: bool = false;
-pub const DEBUG_VERBOSE: bool = false;
-pub const DEBUG_PERF: bool = false;
-
-pub const PIXEL_ON: (u8, u8, u8) = (0x21, 0x31, 0x34);
-pub const PIXEL_OFF: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
-
-pub const CB_8XY_: CB = CB::NEW; // NEW : does not                           |||| OLD : + Set VX to the value of VY
-pub const CB_B_NN: CB = CB::NEW; // NEW : Jump to adress NNN + VX            |||| OLD : Jump to the address NNN plus V0.
-pub const CB_FX1E: CB = CB::NEW; // NEW : + If I overlfow the memory, VF = 1 |||| OLD : does not
-pub const CB_FX_5: CB = CB::NEW; // NEW : is not                             |||| OLD : I is incremented
-
-pub const FONT_SET: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
-#[derive(PartialEq)]
-pub enum CB {
-    // Command behavior
-    NEW,
-    OLD,
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::fonts::{BigFont, Fonts, SmallFont};
+use crate::keymap::{self, KeyMap};
+use crate::quirks::Quirks;
+use crate::rom_database::{self, GameKeys};
+use crate::trace::{TraceFormat, TraceSettings};
+
+pub const USAGE: &str = "Usage: chip [OPTIONS] <ROM>
+       chip disasm [--platform <NAME>] <ROM>    Print the instructions of the rom
+       chip asm <SOURCE> [-o <ROM>]             Assemble a source file into a rom (SOURCE.ch8 by default)
+
+Options:
+  --platform <NAME>       chip8, schip for SUPER-CHIP 1.1 (hires, scrolling...)
+                          or xochip for XO-CHIP (64 KiB, 2 planes, audio pattern) [default: chip8]
+  --ips <N>               Number of instructions executed per second [default: 700]
+  --pixel-on <RRGGBB>     Color of a pixel that is on [default: 213134]
+  --pixel-off <RRGGBB>    Color of a pixel that is off [default: FFFFFF]
+  --pixel-plane2 <RRGGBB> XO-CHIP : color of a pixel that is only on in plane 2 [default: E06030]
+  --pixel-both <RRGGBB>   XO-CHIP : color of a pixel that is on in both planes [default: 803020]
+  --quirks <PRESET>       Behavior of the instructions that changed between interpreters : vip, chip48,
+                          schip1.0, schip1.1, xochip or modern [default: modern, schip1.1 for schip,
+                          xochip for xochip]
+  --quirk <NAME=VALUE>    Changes one quirk of the preset, can be repeated :
+                          vf_reset=on      8XY1/8XY2/8XY3 reset VF to 0
+                          memory=x+1       FX55/FX65 increment I by X + 1 (none, x or x+1)
+                          display_wait=on  DXYN waits for the next frame
+                          clipping=off     The sprites wrap around the screen instead of being cut
+                          shifting=off     8XY6/8XYE shift VY into VX instead of shifting VX
+                          jumping=on       BXNN jumps to XNN + VX instead of BNNN to NNN + V0
+                          i_overflow=on    FX1E sets VF to 1 when I goes past the memory, to 0 otherwise
+                          key_release=off  FX0A returns on the key press instead of the release (CHIP-48, SUPER-CHIP)
+                          stack_depth=12   Nested subroutine calls before a stack overflow (1 to 16)
+                          memory_wrap=off  Stop on the adresses past the end of the memory instead of wrapping
+  --font <NAME>           Font of FX29 : vip, dream6800, eti660 or schip [default: schip]
+  --big-font <NAME>       Font of FX30 : schip (digits only) or xochip [default: schip, xochip for xochip]
+  --database <FILE>       Rom database used before the bundled one to identify the rom
+                          [default: ~/.config/chip/programs.json]
+  --no-database           Don't identify the rom, only use the options given here
+  --layout <NAME>         Keyboard layout of the CHIP-8 keypad : qwerty or azerty [default: azerty]
+  --keymap <FILE>         Key bindings file [default: ~/.config/chip/keys.json]
+  --rewind <SECONDS>      How far back holding Backspace goes, 0 to disable [default: 10]
+  --debugger              Start paused and read debugger commands from the terminal (help to list them)
+  --trace <FILE>          Write every executed instruction with the registers it changed in FILE, - for the terminal
+  --trace-format <NAME>   Format of the trace file : text or json (JSON Lines) [default: text]
+  --trace-range <START-END>
+                          Only trace the instructions at these adresses (hexadecimal), like 200-2FF
+  --trace-opcodes <LIST>  Only trace the instructions starting with these hexadecimal digits, like 8,D,F
+  --trace-buffer <N>      Number of traced instructions kept for the trace command of the debugger
+                          [default: 1000]
+  --debug-perf            Print the time taken by each frame
+  -h, --help              Print this help";
+
+/// Why `LaunchOptions::from_args` did not return options.
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    Help,            // -h or --help, USAGE goes to the standard output
+    Invalid(String), // the message to show the user
+}
+
+impl From<String> for ArgsError {
+    fn from(message: String) -> Self {
+        ArgsError::Invalid(message)
+    }
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "{USAGE}"),
+            ArgsError::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+// The options that are not given are taken from the rom database when the rom is in it
+// (platform, quirks, ips, colors, fonts) by `load_database`, see rom_database.rs
+
+#[derive(Clone)]
+pub struct LaunchOptions {
+    pub rom_path: String,
+    pub platform: Platform,
+
+    pub ips: u64, // instructions per second
+    pub rewind_seconds: u64,
+
+    pub debugger: bool,
+    pub debug_perf: bool,
+    pub trace: Option<TraceSettings>, // given by the --trace options
+
+    pub pixel_on: (u8, u8, u8),
+    pub pixel_off: (u8, u8, u8),
+    pub pixel_plane_2: (u8, u8, u8),
+    pub pixel_both: (u8, u8, u8),
+
+    pub quirks: Quirks,
+    pub fonts: Fonts,
+
+    pub title: Option<String>, // of the rom, when it was found in the database
+    pub game_keys: [GameKeys; 2], // the keys of the rom for the arrows and the buttons of each player
+    pub keymap: KeyMap,
+
+    pub(crate) command_line: CommandLine,
+}
+
+// What the command line asked for that depends on the rom, used by `load_database` and `load_keymap`
+#[derive(Clone, Default)]
+pub(crate) struct CommandLine {
+    given: Vec<String>, // options given on the command line, they win over the database
+    quirks: Option<Quirks>, // the default depends on the platform
+    quirk_settings: Vec<String>,
+    small_font: Option<SmallFont>, // the defaults depend on the platform too
+    big_font: Option<BigFont>,
+    database: Option<PathBuf>,
+    identify: bool,
+    layout: Option<String>,
+    keymap_path: Option<PathBuf>,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        LaunchOptions {
+            rom_path: String::new(),
+            platform: Platform::Chip8,
+            ips: 700,
+            rewind_seconds: 10,
+            debugger: false,
+            debug_perf: false,
+            trace: None,
+            pixel_on: (0x21, 0x31, 0x34),
+            pixel_off: (0xFF, 0xFF, 0xFF),
+            pixel_plane_2: (0xE0, 0x60, 0x30),
+            pixel_both: (0x80, 0x30, 0x20),
+            quirks: Quirks::MODERN,
+            fonts: Fonts::default(),
+            title: None,
+            game_keys: [GameKeys::default(); 2],
+            keymap: KeyMap::layout(keymap::DEFAULT_LAYOUT).unwrap(),
+            command_line: CommandLine::default(),
+        }
+    }
+}
+
+impl LaunchOptions {
+    /// Parses the command line arguments (without the program name), without reading any file.
+    /// Returns `ArgsError::Help` if the help was asked.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut options = LaunchOptions::default();
+        let mut rom_path = None;
+        let mut command_line = CommandLine {
+            database: rom_database::user_database_path(),
+            identify: true,
+            keymap_path: keymap::config_path(),
+            ..CommandLine::default()
+        };
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg.starts_with('-') {
+                command_line.given.push(arg.clone());
+            }
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {name}\n\n{USAGE}"))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ArgsError::Help),
+                "--platform" => options.platform = Platform::parse(&value("--platform")?)?,
+                "--ips" => {
+                    let ips = value("--ips")?;
+                    options.ips = match ips.parse() {
+                        Ok(0) | Err(_) => {
+                            return Err(
+                                format!("Invalid number of instructions per second: {ips}").into()
+                            )
+                        }
+                        Ok(ips) => ips,
+                    };
+                }
+                "--rewind" => {
+                    let seconds = value("--rewind")?;
+                    options.rewind_seconds = seconds
+                        .parse()
+                        .map_err(|_| format!("Invalid number of seconds: {seconds}"))?;
+                }
+                "--pixel-on" => options.pixel_on = parse_color(&value("--pixel-on")?)?,
+                "--pixel-off" => options.pixel_off = parse_color(&value("--pixel-off")?)?,
+                "--pixel-plane2" => options.pixel_plane_2 = parse_color(&value("--pixel-plane2")?)?,
+                "--pixel-both" => options.pixel_both = parse_color(&value("--pixel-both")?)?,
+                "--quirks" => command_line.quirks = Some(Quirks::preset(&value("--quirks")?)?),
+                "--quirk" => command_line.quirk_settings.push(value("--quirk")?),
+                "--font" => command_line.small_font = Some(SmallFont::parse(&value("--font")?)?),
+                "--big-font" => {
+                    command_line.big_font = Some(BigFont::parse(&value("--big-font")?)?)
+                }
+                "--database" => command_line.database = Some(PathBuf::from(value("--database")?)),
+                "--no-database" => command_line.identify = false,
+                "--layout" => {
+                    let name = value("--layout")?;
+                    options.keymap = KeyMap::layout(&name)?;
+                    command_line.layout = Some(name);
+                }
+                "--keymap" => command_line.keymap_path = Some(PathBuf::from(value("--keymap")?)),
+                "--debugger" => options.debugger = true,
+                "--debug-perf" => options.debug_perf = true,
+                "--trace" => trace(&mut options).file = Some(value("--trace")?),
+                "--trace-format" => {
+                    trace(&mut options).format = TraceFormat::parse(&value("--trace-format")?)?
+                }
+                "--trace-range" => trace(&mut options)
+                    .filter
+                    .parse_range(&value("--trace-range")?)?,
+                "--trace-opcodes" => trace(&mut options)
+                    .filter
+                    .parse_classes(&value("--trace-opcodes")?)?,
+                "--trace-buffer" => {
+                    let buffer = value("--trace-buffer")?;
+                    trace(&mut options).buffer = buffer
+                        .parse()
+                        .map_err(|_| format!("Invalid number of instructions: {buffer}"))?;
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unknown option: {arg}\n\n{USAGE}").into())
+                }
+                _ if rom_path.is_none() => rom_path = Some(arg),
+                _ => return Err(format!("Unexpected argument: {arg}\n\n{USAGE}").into()),
+            }
+        }
+
+        options.rom_path = rom_path.ok_or_else(|| format!("Missing rom path\n\n{USAGE}"))?;
+        options.set_quirks_and_fonts(
+            command_line.quirks,
+            command_line.small_font,
+            &command_line,
+        )?;
+        options.command_line = command_line;
+        Ok(options)
+    }
+
+    /// Takes the options that were not given on the command line from the rom database,
+    /// if `rom` is in it. Does nothing with --no-database.
+    pub fn load_database(&mut self, rom: &[u8]) -> Result<(), String> {
+        let command_line = self.command_line.clone();
+        if !command_line.identify || rom.is_empty() {
+            return Ok(());
+        }
+        let Some(settings) = rom_database::identify(rom, command_line.database.clone())? else {
+            return Ok(());
+        };
+        let given = |name: &str| command_line.given.iter().any(|arg| arg == name);
+        if !given("--platform") {
+            self.platform = settings.platform;
+        }
+        if !given("--ips") {
+            self.ips = settings.ips;
+        }
+        let colors = [
+            ("--pixel-off", &mut self.pixel_off),
+            ("--pixel-on", &mut self.pixel_on),
+            ("--pixel-plane2", &mut self.pixel_plane_2),
+            ("--pixel-both", &mut self.pixel_both),
+        ];
+        for ((name, color), database_color) in colors.into_iter().zip(settings.colors) {
+            if !given(name) {
+                *color = database_color;
+            }
+        }
+        self.title = Some(settings.title);
+        self.game_keys = settings.keys;
+
+        let quirks = match command_line.quirks {
+            None if !given("--platform") => Some(settings.quirks),
+            quirks => quirks,
+        };
+        let small_font = command_line.small_font.or(settings.font);
+        self.set_quirks_and_fonts(quirks, small_font, &command_line)
+    }
+
+    /// Reads the keymap file (keys.json) for `rom`, after `load_database` which gives the keys of the rom.
+    pub fn load_keymap(&mut self, rom: &[u8]) -> Result<(), String> {
+        let command_line = &self.command_line;
+        let rom_name = Path::new(&self.rom_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let keymap_path = command_line.keymap_path.clone();
+        let config = keymap::read_config(keymap_path.clone())?;
+        self.keymap = keymap::load(
+            &config,
+            command_line.layout.as_deref(),
+            &self.game_keys,
+            &rom_database::sha1_hex(rom),
+            &rom_name,
+        )
+        .map_err(|err| match &keymap_path {
+            Some(path) => format!("{}:{err}", path.display()),
+            None => err,
+        })?;
+        Ok(())
+    }
+
+    // The quirks and the fonts default to the ones of the platform, then --quirk changes them
+    fn set_quirks_and_fonts(
+        &mut self,
+        quirks: Option<Quirks>,
+        small_font: Option<SmallFont>,
+        command_line: &CommandLine,
+    ) -> Result<(), String> {
+        self.quirks = quirks.unwrap_or(Quirks::for_platform(self.platform));
+        for setting in &command_line.quirk_settings {
+            self.quirks.set(setting)?;
+        }
+        let fonts = Fonts::for_platform(self.platform);
+        self.fonts = Fonts {
+            small: small_font.unwrap_or(fonts.small),
+            big: command_line.big_font.unwrap_or(fonts.big),
+        };
+        Ok(())
+    }
+}
+
+// Any of the --trace options turns the trace on
+fn trace(options: &mut LaunchOptions) -> &mut TraceSettings {
+    options.trace.get_or_insert_with(TraceSettings::default)
+}
+
+pub(crate) fn parse_color(color: &str) -> Result<(u8, u8, u8), String> {
+    let hex = color.trim_start_matches('#');
+    let rgb = match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => rgb,
+        _ => return Err(format!("Invalid color: {color} (expected RRGGBB)")),
+    };
+    Ok(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Platform {
+    Chip8,
+    SuperChip, // SUPER-CHIP 1.1
+    XoChip,    // XO-CHIP, which also has the SUPER-CHIP instructions
+}
+
+impl Platform {
+    pub fn has_schip_instructions(&self) -> bool {
+        *self != Platform::Chip8
+    }
+
+    fn parse(platform: &str) -> Result<Platform, String> {
+        match platform.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Invalid platform: {platform} (expected chip8, schip or xochip)"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<LaunchOptions, ArgsError> {
+        LaunchOptions::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn the_help_is_not_an_invalid_argument() {
+        assert!(matches!(parse(&["--help"]), Err(ArgsError::Help)));
+        assert!(matches!(parse(&["rom", "-h"]), Err(ArgsError::Help)));
+        assert!(matches!(
+            parse(&["--ips", "0", "rom"]),
+            Err(ArgsError::Invalid(message)) if message == "Invalid number of instructions per second: 0"
+        ));
+        assert!(matches!(parse(&["--platform"]), Err(ArgsError::Invalid(_))));
+    }
+
+    #[test]
+    fn the_database_is_only_read_by_load_database() {
+        const TETRIS: &str = "roms/games/Tetris [Fran Dachille, 1991].ch8";
+        let rom = std::fs::read(TETRIS).unwrap();
+        // Parsing doesn't read the rom, the database nor the keymap
+        let mut options = parse(&[
+            "--keymap",
+            "/missing/keys.json",
+            "--database",
+            "/missing/programs.json",
+            "--quirk",
+            "jumping=off",
+            TETRIS,
+        ])
+        .unwrap();
+        assert!(!options.quirks.jumping);
+        assert_eq!((options.title.as_deref(), options.ips), (None, 700));
+
+        options.load_database(&rom).unwrap();
+        assert_eq!(
+            (options.title.as_deref(), options.ips),
+            (Some("Tetris"), 1800)
+        );
+        // The database gives the CHIP-48 quirks, the command line still wins over them
+        assert_eq!(
+            options.quirks,
+            Quirks {
+                jumping: false,
+                ..Quirks::CHIP_48
+            }
+        );
+
+        let mut options = parse(&["--no-database", "--ips", "10", TETRIS]).unwrap();
+        options.load_database(&rom).unwrap();
+        assert_eq!((options.title.as_deref(), options.ips), (None, 10));
+    }
 }
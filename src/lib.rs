@@ -0,0 +1,25 @@
+#![allow(non_snake_case)]
+pub mod assembler;
+pub mod chip8;
+pub mod constants;
+pub mod custom_errors;
+pub mod debugger;
+pub mod disassembler;
+pub mod fonts;
+pub mod headless;
+pub mod instructions;
+pub mod json;
+pub mod keymap;
+pub mod keys;
+pub mod launch_options;
+pub mod memory;
+pub mod opcodes;
+pub mod quirks;
+pub mod rewind;
+pub mod rom_database;
+pub mod savestate;
+pub mod screen;
+pub mod stack;
+pub mod trace;
+
+pub use chip8::Chip8;
@@ -1,120 +1,279 @@
-#![allow(non_snake_case)]
-mod constants;
-mod custom_errors;
+mod audio;
 mod display;
 mod events;
-mod instructions;
-mod launch_options;
-mod memory;
-mod screen;
-mod audio;
 
-use constants::*;
-use launch_options::*;
+use events::Hotkey;
+
+use chip::constants::TIMER_FREQUENCY;
+use chip::custom_errors::EmulatorError;
+use chip::debugger::{self, Debugger};
+use chip::launch_options::*;
+use chip::rewind::Rewind;
+use chip::savestate::Snapshot;
+use chip::trace::{Trace, TraceSettings};
+use chip::Chip8;
+use chip::{assembler, disassembler};
 
 use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::{mpsc, Arc},
     thread,
-    time::{Duration, Instant}, sync::{Arc, atomic::{AtomicBool, Ordering}},
+    time::{Duration, Instant},
 };
 
-fn load_font(memory: &mut memory::Memory) {
-    for (i, byte) in FONT_SET.iter().enumerate() {
-        memory.write(i as u16 + FONT_ADRESS, *byte);
-    }
-}
+const FPS: u64 = TIMER_FREQUENCY; // one frame displayed per timer tick
+const SAVE_SLOTS: u8 = 10;
 
 fn main() {
-    // INIT DISPLAY
-    let mut screen = screen::Screen::new();
+    // LAUNCH OPTIONS
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "asm") {
+        assemble(&args[1..]);
+        return;
+    }
+    let disasm = args.first().is_some_and(|arg| arg == "disasm");
+    if disasm {
+        args.remove(0);
+    }
+    let mut options = match LaunchOptions::from_args(args) {
+        Ok(options) => options,
+        Err(ArgsError::Help) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(ArgsError::Invalid(message)) => {
+            eprintln!("{message}");
+            process::exit(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom_path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            let reason = format!("could not read {} : {err}", options.rom_path);
+            eprintln!("{}", EmulatorError::BadRom(reason));
+            process::exit(1);
+        }
+    };
+
+    // The disassembler only needs the platform from the database, not the keymap
+    if let Err(message) = options.load_database(&rom) {
+        eprintln!("{message}");
+        process::exit(2);
+    }
+
+    // DISASSEMBLER
+    if disasm {
+        print!("{}", disassembler::disassemble(&rom, options.platform));
+        return;
+    }
 
-    let (sdl_context, mut canvas) = display::init().expect("Could not init display");
+    if let Err(message) = options.load_keymap(&rom) {
+        eprintln!("{message}");
+        process::exit(2);
+    }
 
-    // INIT EVENTS
-    let mut keys_state = events::KeysState::new();
+    if let Some(title) = &options.title {
+        println!("{title}");
+    }
 
-    // INIT MEMORY
-    let mut memory: memory::Memory = memory::Memory::new();
-    memory.load_rom(ROM_PATH).unwrap();
-    load_font(&mut memory);
+    // INIT DISPLAY
+    let (sdl_context, mut canvas) = display::init(&options).expect("Could not init display");
 
-    struct PtrMem(*mut memory::Memory);
-    unsafe impl Send for PtrMem {}
-    let ptr_mem_delay = PtrMem(&mut memory as *mut memory::Memory);
-    let ptr_mem_sound = PtrMem(&mut memory as *mut memory::Memory);
+    let mut controllers =
+        events::Controllers::new(&sdl_context).expect("Could not init the game controllers");
 
-    let mut stack = Vec::<u16>::new(); // Stack of adresses used to call subroutines or return from them
-    let mut pc: u16 = 0x200; // program counter
+    // INIT MACHINE
+    let mut chip8 = Chip8::new(options.clone());
+    if let Err(err) = chip8.load_rom(&rom) {
+        eprintln!("{err}");
+        process::exit(1);
+    }
 
+    let beeper = Arc::new(audio::Beeper::new());
+    let beeper_clone = beeper.clone();
     thread::spawn(move || {
-        let _ = &ptr_mem_delay;
-        let memory = unsafe { &mut *ptr_mem_delay.0 };
-        loop {
-            let timer = memory.read_delay_timer();
-            if timer > 0 {
-                memory.decrement_delay_timer();
-
-                thread::sleep(Duration::from_millis(16));
-            }
-        }
+        audio::play_beep(beeper_clone);
     });
-    thread::spawn(move || {
-        let _ = &ptr_mem_sound;
-        let memory = unsafe { &mut *ptr_mem_sound.0 };
-        let condition = Arc::new(AtomicBool::new(false));
-        let condition_clone = condition.clone();
-        thread::spawn(move || {audio::play_beep(condition_clone);});
-        loop {
-            let timer = memory.read_sound_timer();
-            if timer > 0 {
-                condition.store(true, Ordering::Relaxed);
-                memory.decrement_sound_timer();
-
-                thread::sleep(Duration::from_millis(16));
-            } else {
-                condition.store(false, Ordering::Relaxed);
+
+    // DEBUGGER
+    // The commands are read from the terminal in another thread so the window keeps being refreshed
+    let mut debugger = None;
+    let (commands_sender, commands) = mpsc::channel();
+    if options.debugger {
+        debugger = Some(Debugger::new(true));
+        println!("{}\n\n{}", debugger::HELP, debugger::state(&chip8));
+        thread::spawn(move || {
+            for line in io::stdin().lines().map_while(Result::ok) {
+                if commands_sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // TRACE
+    // Always kept in memory with the debugger, for its trace command
+    let trace_settings =
+        (options.trace.clone()).or_else(|| options.debugger.then(TraceSettings::default));
+    if let Some(settings) = trace_settings {
+        match Trace::new(&settings) {
+            Ok(trace) => chip8.set_trace(Some(trace)),
+            Err(err) => {
+                eprintln!("{err}");
+                process::exit(1);
             }
         }
-    });
+    }
 
     // GAME LOOP
-    if DEBUG {
-        println!();
-        println!(" adr  | instr  | effect");
-        println!("------+--------+--------------------------------");
-    }
+    let frame_duration = Duration::from_secs_f64(1.0 / FPS as f64);
+    let mut next_frame = Instant::now();
+    let mut slot = 0; // save state slot used by the hotkeys
+    let mut rewind = Rewind::new((options.rewind_seconds * FPS) as usize);
+    let mut rewinding = false;
 
     loop {
         let start = Instant::now();
 
         // Only way it could be Err is if the user wants to quit the game
-        if events::update(&sdl_context, &mut keys_state).is_err() {
+        let Ok(hotkeys) =
+            events::update(&sdl_context, &mut chip8, &options.keymap, &mut controllers)
+        else {
+            break;
+        };
+        for hotkey in hotkeys {
+            match hotkey {
+                Hotkey::SaveState => save_state(&chip8, slot),
+                Hotkey::LoadState => load_state(&mut chip8, slot),
+                Hotkey::PreviousSlot | Hotkey::NextSlot => {
+                    slot = match hotkey {
+                        Hotkey::NextSlot => (slot + 1) % SAVE_SLOTS,
+                        _ => (slot + SAVE_SLOTS - 1) % SAVE_SLOTS,
+                    };
+                    println!("Save state slot {slot}");
+                }
+                Hotkey::Rewind(held) => rewinding = held,
+            }
+        }
+
+        // REWIND
+        // One frame back per displayed frame while the key is held, the game continues from there
+        if rewinding {
+            rewind.rewind(&mut chip8);
+            beeper.update(false, chip8.audio_pattern());
+            if let Some(screen) = chip8.take_frame() {
+                display::display(&mut canvas, screen, &options).expect("Error while displaying");
+            }
+            next_frame += frame_duration;
+            if let Some(time_left_frame) = next_frame.checked_duration_since(Instant::now()) {
+                thread::sleep(time_left_frame);
+            }
+            continue;
+        }
+
+        if let Some(debugger) = &mut debugger {
+            // The debugger pauses on the instruction that failed, to inspect the state
+            for command in commands.try_iter() {
+                match debugger.execute(&mut chip8, &command) {
+                    Ok(output) => println!("{output}"),
+                    Err(err) => println!("{err}\n\n{}", debugger::state(&chip8)),
+                }
+            }
+            match debugger.run_frame(&mut chip8) {
+                Ok(true) => println!("{}", debugger::state(&chip8)),
+                Ok(false) => {}
+                Err(err) => println!("{err}\n\n{}", debugger::state(&chip8)),
+            }
+            for hit in chip8.take_watch_log() {
+                println!("Watchpoint: {hit}");
+            }
+        } else if let Err(err) = chip8.run_frame() {
+            eprintln!("{err}\n\n{}", debugger::state(&chip8));
+            process::exit(1);
+        }
+        let paused = debugger.as_ref().is_some_and(Debugger::is_paused);
+        if !paused {
+            rewind.record(&chip8);
+        }
+        if chip8.has_exited() {
             break;
         }
+        beeper.update(chip8.is_beeping() && !paused, chip8.audio_pattern());
 
-        instructions::decode(
-            &mut pc,
-            &mut stack,
-            &mut screen,
-            &mut canvas,
-            &mut memory,
-            &keys_state,
-        )
-        .expect("Instruction not implemented");
-
-        // To have IPS instructions per second
-        let elapsed = start.elapsed();
-        if let Some(time_left_frame) =
-            Duration::from_secs_f64(1.0 / IPS as f64).checked_sub(elapsed)
-        {
-            thread::sleep(time_left_frame);
+        if let Some(screen) = chip8.take_frame() {
+            display::display(&mut canvas, screen, &options).expect("Error while displaying");
         }
-        if DEBUG_PERF {
-            let warning = if Duration::from_secs_f64(1.0 / IPS as f64) < elapsed {
+
+        let elapsed = start.elapsed();
+        if options.debug_perf {
+            let warning = if frame_duration < elapsed {
                 "/!\\/!\\/!\\  "
             } else {
                 ""
             };
-            println!("{warning}{:?} | {:?}", elapsed, start.elapsed());
+            println!("{warning}{:?} | {:?}", elapsed, frame_duration);
+        }
+
+        // Wait for the next frame, without drifting if one frame was late
+        next_frame += frame_duration;
+        match next_frame.checked_duration_since(Instant::now()) {
+            Some(time_left_frame) => thread::sleep(time_left_frame),
+            None => next_frame = Instant::now(),
+        }
+    }
+}
+
+// chip asm <SOURCE> [-o <ROM>], the rom is written next to the source by default
+fn assemble(args: &[String]) {
+    let (source, rom_path) = match args {
+        [source] => (source, Path::new(source).with_extension("ch8")),
+        [source, option, rom_path] if option == "-o" => (source, PathBuf::from(rom_path)),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    };
+    let rom = match assembler::assemble_file(Path::new(source)) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+    if let Err(err) = fs::write(&rom_path, rom) {
+        eprintln!("Could not write {} : {err}", rom_path.display());
+        process::exit(1);
+    }
+}
+
+// SAVE STATES
+// Slot N of a rom is saved next to it, in <ROM>.stateN
+
+fn state_path(chip8: &Chip8, slot: u8) -> String {
+    format!("{}.state{slot}", chip8.options().rom_path)
+}
+
+fn save_state(chip8: &Chip8, slot: u8) {
+    let path = state_path(chip8, slot);
+    match fs::write(&path, chip8.snapshot().to_bytes()) {
+        Ok(()) => println!("State saved in {path}"),
+        Err(err) => eprintln!("Could not save the state in {path} : {err}"),
+    }
+}
+
+fn load_state(chip8: &mut Chip8, slot: u8) {
+    let path = state_path(chip8, slot);
+    let snapshot = fs::read(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| Snapshot::from_bytes(&bytes));
+    match snapshot {
+        Ok(snapshot) => {
+            chip8.restore(snapshot);
+            println!("State loaded from {path}");
         }
+        Err(err) => eprintln!("Could not load the state from {path} : {err}"),
     }
 }
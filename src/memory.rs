@@ -1,79 +1,185 @@
-use std::fs::File;
-use std::io::prelude::*;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::cell::RefCell;
+use std::fmt;
 
+use crate::constants::*;
+use crate::custom_errors::EmulatorError;
+use crate::savestate::Reader;
+
+/// How an instruction touched the memory.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    Execute, // fetched as (part of) an instruction
+}
+
+/// Watches the accesses to the adresses from `start` to `end` (included).
+#[derive(Clone, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub accesses: Vec<Access>,
+    pub log: bool, // only record the accesses, without pausing
+}
+
+/// An access to a watched adress by the instruction at `pc`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WatchHit {
+    pub pc: u16,
+    pub access: Access,
+    pub adress: u16,
+    pub value: u8,
+    pub log: bool, // every watchpoint of the adress is in log mode
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (pc, adress, value) = (self.pc, self.adress, self.value);
+        match self.access {
+            Access::Read => write!(f, "0x{pc:03X} reads 0x{value:02X} at 0x{adress:03X}"),
+            Access::Write => write!(f, "0x{pc:03X} writes 0x{value:02X} at 0x{adress:03X}"),
+            Access::Execute => write!(f, "0x{pc:03X} executes 0x{adress:03X} (0x{value:02X})"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Memory {
-    data: [u8; 4096],
+    data: Vec<u8>,
     registers: [u8; 16],
     adress_register: u16,
-    delay_timer: AtomicU8,
-    sound_timer: AtomicU8,
+    delay_timer: u8,
+    sound_timer: u8,
+    rpl_flags: [u8; 16], // SUPER-CHIP user flags, saved and loaded by FX75 / FX85
+    audio_pattern: [u8; 16], // XO-CHIP 1-bit samples played by the beeper, loaded by F002
+    pitch: u8,           // XO-CHIP playback rate of the audio pattern, set by FX3A
+    watchpoints: Vec<Watchpoint>,
+    hits: RefCell<Vec<WatchHit>>, // since the last take_hits, the reads also record them
 }
 
 impl Memory {
     pub fn new() -> Memory {
+        Memory::with_size(MEMORY_SIZE)
+    }
+
+    pub fn with_size(size: usize) -> Memory {
         Memory {
-            data: [0; 4096],
+            data: vec![0; size],
             registers: [0; 16],
             adress_register: 0,
-            delay_timer: AtomicU8::new(0),
-            sound_timer: AtomicU8::new(0),
+            delay_timer: 0,
+            sound_timer: 0,
+            rpl_flags: [0; 16],
+            audio_pattern: DEFAULT_AUDIO_PATTERN,
+            pitch: DEFAULT_PITCH,
+            watchpoints: Vec::new(),
+            hits: RefCell::new(Vec::new()),
         }
     }
 
     // SETUP
 
-    pub fn load_rom(&mut self, rom: &str) -> Result<(), String> {
-        let mut file = File::open(rom).unwrap();
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).unwrap();
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmulatorError> {
+        if rom.is_empty() {
+            return Err(EmulatorError::BadRom("the file is empty".to_string()));
+        }
         // write the memory from 0x200 (because historically the first 512 bytes were reserved for the interpreter)
-        for (i, byte) in buffer.iter().enumerate() {
-            self.data[0x200 + i] = *byte;
+        let max = self.data.len() - 0x200;
+        if rom.len() > max {
+            return Err(EmulatorError::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
         }
+        self.data[0x200..0x200 + rom.len()].copy_from_slice(rom);
         Ok(())
     }
 
     // DATA
+    // The adresses past the end wrap around the memory, the instructions that trap instead
+    // (see the memory_wrap quirk) check them before
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
 
     pub fn read(&self, address: u16) -> u8 {
-        self.data[address as usize]
+        let value = self.peek(address);
+        self.watch(Access::Read, address, value);
+        value
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
-        self.data[address as usize] = value;
+        let size = self.data.len();
+        self.data[address as usize % size] = value;
+        self.watch(Access::Write, address, value);
     }
 
     pub fn read_word(&self, address: u16) -> u16 {
-        let high = self.data[address as usize] as u16;
-        let low = self.data[address as usize + 1] as u16;
+        let high = self.read(address) as u16;
+        let low = self.read(address.wrapping_add(1)) as u16;
         (high << 8) | low
     }
 
+    /// Reads the instruction at `address`, the watchpoints see an execution instead of a read.
+    pub fn fetch(&self, address: u16) -> u16 {
+        let (high, low) = (self.peek(address), self.peek(address.wrapping_add(1)));
+        self.watch(Access::Execute, address, high);
+        self.watch(Access::Execute, address.wrapping_add(1), low);
+        (high as u16) << 8 | low as u16
+    }
+
+    /// Reads without triggering the watchpoints, for the debugger.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.data[address as usize % self.data.len()]
+    }
+
+    pub fn peek_word(&self, address: u16) -> u16 {
+        (self.peek(address) as u16) << 8 | self.peek(address.wrapping_add(1)) as u16
+    }
+
     // TIMERS
 
     pub fn read_delay_timer(&self) -> u8 {
-        self.delay_timer.load(Ordering::Relaxed)
+        self.delay_timer
     }
 
     pub fn write_delay_timer(&mut self, value: u8) {
-        self.delay_timer.store(value, Ordering::Relaxed);
+        self.delay_timer = value;
     }
 
     pub fn decrement_delay_timer(&mut self) {
-        self.delay_timer.fetch_sub(1, Ordering::Relaxed);
+        self.delay_timer = self.delay_timer.saturating_sub(1);
     }
 
     pub fn read_sound_timer(&self) -> u8 {
-        self.sound_timer.load(Ordering::Relaxed)
+        self.sound_timer
     }
 
     pub fn write_sound_timer(&mut self, value: u8) {
-        self.sound_timer.store(value, Ordering::Relaxed);
+        self.sound_timer = value;
     }
 
     pub fn decrement_sound_timer(&mut self) {
-        self.sound_timer.fetch_sub(1, Ordering::Relaxed);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // AUDIO
+
+    pub fn read_audio_pattern(&self) -> [u8; 16] {
+        self.audio_pattern
+    }
+
+    pub fn write_audio_pattern(&mut self, pattern: [u8; 16]) {
+        self.audio_pattern = pattern;
+    }
+
+    pub fn read_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn write_pitch(&mut self, value: u8) {
+        self.pitch = value;
     }
 
     // REGISTERS
@@ -94,6 +200,101 @@ impl Memory {
         self.registers[index] = value;
     }
 
+    pub fn read_rpl_flag(&self, index: usize) -> u8 {
+        self.rpl_flags[index]
+    }
+
+    pub fn write_rpl_flag(&mut self, index: usize, value: u8) {
+        self.rpl_flags[index] = value;
+    }
+
+    // SAVE STATES (format in savestate.rs)
+
+    pub(crate) fn save(&self, out: &mut Vec<u8>) {
+        out.extend((self.data.len() as u32).to_be_bytes());
+        out.extend(&self.data);
+        out.extend(self.registers);
+        out.extend(self.adress_register.to_be_bytes());
+        out.extend([self.delay_timer, self.sound_timer]);
+        out.extend(self.rpl_flags);
+        out.extend(self.audio_pattern);
+        out.push(self.pitch);
+    }
+
+    pub(crate) fn load(reader: &mut Reader) -> Result<Memory, String> {
+        let size = reader.u32()? as usize;
+        if size != MEMORY_SIZE && size != XO_MEMORY_SIZE {
+            return Err(format!("Invalid memory size in save state: {size}"));
+        }
+        Ok(Memory {
+            data: reader.bytes(size)?.to_vec(),
+            registers: reader.array()?,
+            adress_register: reader.u16()?,
+            delay_timer: reader.u8()?,
+            sound_timer: reader.u8()?,
+            rpl_flags: reader.array()?,
+            audio_pattern: reader.array()?,
+            pitch: reader.u8()?,
+            watchpoints: Vec::new(),
+            hits: RefCell::new(Vec::new()),
+        })
+    }
+
+    // WATCHPOINTS
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes the watchpoints starting at `start`, returns false if there were none.
+    pub fn remove_watchpoints(&mut self, start: u16) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.start != start);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Moves the watchpoints to another memory, a restored one
+    pub(crate) fn move_watchpoints(&mut self, to: &mut Memory) {
+        to.watchpoints = std::mem::take(&mut self.watchpoints);
+    }
+
+    /// The accesses to the watched adresses since the last call, made by the instruction at `pc`.
+    pub(crate) fn take_hits(&self, pc: u16) -> Vec<WatchHit> {
+        let mut hits = self.hits.take();
+        for hit in &mut hits {
+            hit.pc = pc;
+        }
+        hits
+    }
+
+    fn watch(&self, access: Access, adress: u16, value: u8) {
+        // Most of the time there are no watchpoints
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        let adress = (adress as usize % self.data.len()) as u16;
+        let mut watched = self.watchpoints.iter().filter(|watchpoint| {
+            (watchpoint.start..=watchpoint.end).contains(&adress)
+                && watchpoint.accesses.contains(&access)
+        });
+        let Some(first) = watched.next() else {
+            return;
+        };
+        let log = first.log && watched.all(|watchpoint| watchpoint.log);
+        self.hits.borrow_mut().push(WatchHit {
+            pc: 0,
+            access,
+            adress,
+            value,
+            log,
+        });
+    }
+
     // DEBUG
 
     #[allow(dead_code)]
@@ -109,3 +310,97 @@ impl Memory {
         println!();
     }
 }
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_rom_checks_the_size() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.load_rom(&[0xAB; 0xE00]), Ok(()));
+        assert_eq!(memory.read(0xFFF), 0xAB);
+        assert_eq!(
+            memory.load_rom(&[0; 0xE01]),
+            Err(EmulatorError::RomTooLarge {
+                size: 0xE01,
+                max: 0xE00
+            })
+        );
+        assert!(matches!(
+            memory.load_rom(&[]),
+            Err(EmulatorError::BadRom(_))
+        ));
+        assert_eq!(
+            Memory::with_size(XO_MEMORY_SIZE).load_rom(&[0; 0xE01]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn the_adresses_wrap_around_the_memory() {
+        let mut memory = Memory::new();
+        memory.write(0xFFF, 0x12);
+        memory.write(0x1000, 0x34);
+        assert_eq!(memory.read_word(0xFFF), 0x1234);
+        assert_eq!(memory.read(0x2000), 0x34);
+    }
+
+    #[test]
+    fn watchpoints_record_the_accesses() {
+        let mut memory = Memory::new();
+        memory.add_watchpoint(Watchpoint {
+            start: 0x300,
+            end: 0x301,
+            accesses: vec![Access::Write, Access::Execute],
+            log: false,
+        });
+        memory.add_watchpoint(Watchpoint {
+            start: 0x301,
+            end: 0x301,
+            accesses: vec![Access::Read],
+            log: true,
+        });
+        memory.write(0x300, 0x12);
+        memory.write(0x302, 0x34);
+        memory.read(0x300);
+        memory.read(0x1301);
+        memory.fetch(0x2FF);
+        assert_eq!(memory.peek_word(0x300), 0x1200);
+        assert_eq!(
+            memory.take_hits(0x204),
+            [
+                WatchHit {
+                    pc: 0x204,
+                    access: Access::Write,
+                    adress: 0x300,
+                    value: 0x12,
+                    log: false
+                },
+                WatchHit {
+                    pc: 0x204,
+                    access: Access::Read,
+                    adress: 0x301,
+                    value: 0,
+                    log: true
+                },
+                WatchHit {
+                    pc: 0x204,
+                    access: Access::Execute,
+                    adress: 0x300,
+                    value: 0x12,
+                    log: false
+                },
+            ]
+        );
+        assert!(memory.take_hits(0x206).is_empty());
+        assert!(memory.remove_watchpoints(0x301));
+        assert!(!memory.remove_watchpoints(0x301));
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+
+use crate::launch_options::Platform;
+
+/// An instruction decoded from its 2 bytes, shared by the interpreter and the disassembler.
+/// X and Y are register indexes, N, NN and NNN the values in the low bits of the instruction.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Opcode {
+    Sys(u16),            // 0NNN  machine code routine, only 0230 is known (64x64 hires clear)
+    Cls,                 // 00E0
+    Ret,                 // 00EE
+    ScrollDown(u8),      // 00CN  SUPER-CHIP
+    ScrollUp(u8),        // 00DN  XO-CHIP
+    ScrollRight,         // 00FB  SUPER-CHIP
+    ScrollLeft,          // 00FC  SUPER-CHIP
+    Exit,                // 00FD  SUPER-CHIP
+    Lores,               // 00FE  SUPER-CHIP
+    Hires,               // 00FF  SUPER-CHIP
+    Jump(u16),           // 1NNN
+    Call(u16),           // 2NNN
+    SkipEqByte(u8, u8),  // 3XNN
+    SkipNeByte(u8, u8),  // 4XNN
+    SkipEq(u8, u8),      // 5XY0
+    SaveRange(u8, u8),   // 5XY2  XO-CHIP
+    LoadRange(u8, u8),   // 5XY3  XO-CHIP
+    SetByte(u8, u8),     // 6XNN
+    AddByte(u8, u8),     // 7XNN
+    Set(u8, u8),         // 8XY0
+    Or(u8, u8),          // 8XY1
+    And(u8, u8),         // 8XY2
+    Xor(u8, u8),         // 8XY3
+    Add(u8, u8),         // 8XY4
+    Sub(u8, u8),         // 8XY5
+    ShiftRight(u8, u8),  // 8XY6
+    SubN(u8, u8),        // 8XY7
+    ShiftLeft(u8, u8),   // 8XYE
+    SkipNe(u8, u8),      // 9XY0
+    SetI(u16),           // ANNN
+    JumpOffset(u8, u16), // BNNN (or BXNN)
+    Random(u8, u8),      // CXNN
+    Draw(u8, u8, u8),    // DXYN
+    SkipKey(u8),         // EX9E
+    SkipNotKey(u8),      // EXA1
+    SetILong,            // F000 NNNN  XO-CHIP, NNNN is the next 2 bytes
+    Planes(u8),          // FN01  XO-CHIP
+    Audio,               // F002  XO-CHIP
+    GetDelay(u8),        // FX07
+    WaitKey(u8),         // FX0A
+    SetDelay(u8),        // FX15
+    SetSound(u8),        // FX18
+    AddI(u8),            // FX1E
+    Font(u8),            // FX29
+    BigFont(u8),         // FX30  SUPER-CHIP
+    Pitch(u8),           // FX3A  XO-CHIP
+    Bcd(u8),             // FX33
+    Store(u8),           // FX55
+    Load(u8),            // FX65
+    StoreFlags(u8),      // FX75  SUPER-CHIP
+    LoadFlags(u8),       // FX85  SUPER-CHIP
+}
+
+impl Opcode {
+    /// None if the instruction doesn't exist on this platform.
+    pub fn decode(instruction: u16, platform: Platform) -> Option<Opcode> {
+        let schip = platform.has_schip_instructions();
+        let xo = platform == Platform::XoChip;
+
+        let X = ((instruction & 0x0F00) >> 8) as u8;
+        let Y = ((instruction & 0x00F0) >> 4) as u8;
+        let N = (instruction & 0x000F) as u8;
+        let NN = (instruction & 0x00FF) as u8;
+        let NNN = instruction & 0x0FFF;
+
+        let opcode = match (instruction & 0xF000) >> 12 {
+            0x0 => match instruction {
+                0x00E0 => Opcode::Cls,
+                0x00EE => Opcode::Ret,
+                0x00C0..=0x00CF if schip => Opcode::ScrollDown(N),
+                0x00D0..=0x00DF if xo => Opcode::ScrollUp(N),
+                0x00FB if schip => Opcode::ScrollRight,
+                0x00FC if schip => Opcode::ScrollLeft,
+                0x00FD if schip => Opcode::Exit,
+                0x00FE if schip => Opcode::Lores,
+                0x00FF if schip => Opcode::Hires,
+                _ => Opcode::Sys(NNN),
+            },
+            0x1 => Opcode::Jump(NNN),
+            0x2 => Opcode::Call(NNN),
+            0x3 => Opcode::SkipEqByte(X, NN),
+            0x4 => Opcode::SkipNeByte(X, NN),
+            0x5 => match N {
+                0 => Opcode::SkipEq(X, Y),
+                2 if xo => Opcode::SaveRange(X, Y),
+                3 if xo => Opcode::LoadRange(X, Y),
+                _ => return None,
+            },
+            0x6 => Opcode::SetByte(X, NN),
+            0x7 => Opcode::AddByte(X, NN),
+            0x8 => match N {
+                0x0 => Opcode::Set(X, Y),
+                0x1 => Opcode::Or(X, Y),
+                0x2 => Opcode::And(X, Y),
+                0x3 => Opcode::Xor(X, Y),
+                0x4 => Opcode::Add(X, Y),
+                0x5 => Opcode::Sub(X, Y),
+                0x6 => Opcode::ShiftRight(X, Y),
+                0x7 => Opcode::SubN(X, Y),
+                0xE => Opcode::ShiftLeft(X, Y),
+                _ => return None,
+            },
+            0x9 if N == 0 => Opcode::SkipNe(X, Y),
+            0xA => Opcode::SetI(NNN),
+            0xB => Opcode::JumpOffset(X, NNN),
+            0xC => Opcode::Random(X, NN),
+            0xD => Opcode::Draw(X, Y, N),
+            0xE => match NN {
+                0x9E => Opcode::SkipKey(X),
+                0xA1 => Opcode::SkipNotKey(X),
+                _ => return None,
+            },
+            0xF => match NN {
+                0x00 if xo && X == 0 => Opcode::SetILong,
+                0x01 if xo => Opcode::Planes(X),
+                0x02 if xo && X == 0 => Opcode::Audio,
+                0x07 => Opcode::GetDelay(X),
+                0x0A => Opcode::WaitKey(X),
+                0x15 => Opcode::SetDelay(X),
+                0x18 => Opcode::SetSound(X),
+                0x1E => Opcode::AddI(X),
+                0x29 => Opcode::Font(X),
+                0x30 if schip => Opcode::BigFont(X),
+                0x33 => Opcode::Bcd(X),
+                0x3A if xo => Opcode::Pitch(X),
+                0x55 => Opcode::Store(X),
+                0x65 => Opcode::Load(X),
+                0x75 if schip => Opcode::StoreFlags(X),
+                0x85 if schip => Opcode::LoadFlags(X),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(opcode)
+    }
+
+    /// Number of bytes of the instruction, 4 for F000 NNNN.
+    pub fn size(&self) -> u16 {
+        if *self == Opcode::SetILong {
+            4
+        } else {
+            2
+        }
+    }
+
+    /// The adress this instruction jumps to, calls or points I at, if it is known statically.
+    pub fn target(&self) -> Option<u16> {
+        match self {
+            Opcode::Jump(NNN) | Opcode::Call(NNN) | Opcode::SetI(NNN) => Some(*NNN),
+            _ => None,
+        }
+    }
+
+    /// The instruction may skip the next one.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Opcode::SkipEqByte(..)
+                | Opcode::SkipNeByte(..)
+                | Opcode::SkipEq(..)
+                | Opcode::SkipNe(..)
+                | Opcode::SkipKey(_)
+                | Opcode::SkipNotKey(_)
+        )
+    }
+}
+
+/// The mnemonic form, for example `LD V3, 0x12` or `DRW V0, V1, 5`.
+/// The adress of F000 NNNN is in the next 2 bytes so it is written `LD I, LONG`.
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::Sys(NNN) => write!(f, "SYS 0x{NNN:03X}"),
+            Opcode::Cls => write!(f, "CLS"),
+            Opcode::Ret => write!(f, "RET"),
+            Opcode::ScrollDown(N) => write!(f, "SCD {N}"),
+            Opcode::ScrollUp(N) => write!(f, "SCU {N}"),
+            Opcode::ScrollRight => write!(f, "SCR"),
+            Opcode::ScrollLeft => write!(f, "SCL"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::Lores => write!(f, "LOW"),
+            Opcode::Hires => write!(f, "HIGH"),
+            Opcode::Jump(NNN) => write!(f, "JP 0x{NNN:03X}"),
+            Opcode::Call(NNN) => write!(f, "CALL 0x{NNN:03X}"),
+            Opcode::SkipEqByte(X, NN) => write!(f, "SE V{X:X}, 0x{NN:02X}"),
+            Opcode::SkipNeByte(X, NN) => write!(f, "SNE V{X:X}, 0x{NN:02X}"),
+            Opcode::SkipEq(X, Y) => write!(f, "SE V{X:X}, V{Y:X}"),
+            Opcode::SaveRange(X, Y) => write!(f, "SAVE V{X:X}, V{Y:X}"),
+            Opcode::LoadRange(X, Y) => write!(f, "LOAD V{X:X}, V{Y:X}"),
+            Opcode::SetByte(X, NN) => write!(f, "LD V{X:X}, 0x{NN:02X}"),
+            Opcode::AddByte(X, NN) => write!(f, "ADD V{X:X}, 0x{NN:02X}"),
+            Opcode::Set(X, Y) => write!(f, "LD V{X:X}, V{Y:X}"),
+            Opcode::Or(X, Y) => write!(f, "OR V{X:X}, V{Y:X}"),
+            Opcode::And(X, Y) => write!(f, "AND V{X:X}, V{Y:X}"),
+            Opcode::Xor(X, Y) => write!(f, "XOR V{X:X}, V{Y:X}"),
+            Opcode::Add(X, Y) => write!(f, "ADD V{X:X}, V{Y:X}"),
+            Opcode::Sub(X, Y) => write!(f, "SUB V{X:X}, V{Y:X}"),
+            Opcode::ShiftRight(X, Y) => write!(f, "SHR V{X:X}, V{Y:X}"),
+            Opcode::SubN(X, Y) => write!(f, "SUBN V{X:X}, V{Y:X}"),
+            Opcode::ShiftLeft(X, Y) => write!(f, "SHL V{X:X}, V{Y:X}"),
+            Opcode::SkipNe(X, Y) => write!(f, "SNE V{X:X}, V{Y:X}"),
+            Opcode::SetI(NNN) => write!(f, "LD I, 0x{NNN:03X}"),
+            Opcode::JumpOffset(_, NNN) => write!(f, "JP V0, 0x{NNN:03X}"),
+            Opcode::Random(X, NN) => write!(f, "RND V{X:X}, 0x{NN:02X}"),
+            Opcode::Draw(X, Y, N) => write!(f, "DRW V{X:X}, V{Y:X}, {N}"),
+            Opcode::SkipKey(X) => write!(f, "SKP V{X:X}"),
+            Opcode::SkipNotKey(X) => write!(f, "SKNP V{X:X}"),
+            Opcode::SetILong => write!(f, "LD I, LONG"),
+            Opcode::Planes(N) => write!(f, "PLANE {N}"),
+            Opcode::Audio => write!(f, "AUDIO"),
+            Opcode::GetDelay(X) => write!(f, "LD V{X:X}, DT"),
+            Opcode::WaitKey(X) => write!(f, "LD V{X:X}, K"),
+            Opcode::SetDelay(X) => write!(f, "LD DT, V{X:X}"),
+            Opcode::SetSound(X) => write!(f, "LD ST, V{X:X}"),
+            Opcode::AddI(X) => write!(f, "ADD I, V{X:X}"),
+            Opcode::Font(X) => write!(f, "LD F, V{X:X}"),
+            Opcode::BigFont(X) => write!(f, "LD HF, V{X:X}"),
+            Opcode::Pitch(X) => write!(f, "PITCH V{X:X}"),
+            Opcode::Bcd(X) => write!(f, "LD B, V{X:X}"),
+            Opcode::Store(X) => write!(f, "LD [I], V{X:X}"),
+            Opcode::Load(X) => write!(f, "LD V{X:X}, [I]"),
+            Opcode::StoreFlags(X) => write!(f, "LD R, V{X:X}"),
+            Opcode::LoadFlags(X) => write!(f, "LD V{X:X}, R"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_instructions_depend_on_the_platform() {
+        let decode = |instruction| {
+            [Platform::Chip8, Platform::SuperChip, Platform::XoChip]
+                .map(|platform| Opcode::decode(instruction, platform))
+        };
+        assert_eq!(
+            decode(0x00FF),
+            [
+                Some(Opcode::Sys(0xFF)),
+                Some(Opcode::Hires),
+                Some(Opcode::Hires)
+            ]
+        );
+        assert_eq!(
+            decode(0x00D3),
+            [
+                Some(Opcode::Sys(0xD3)),
+                Some(Opcode::Sys(0xD3)),
+                Some(Opcode::ScrollUp(3))
+            ]
+        );
+        assert_eq!(decode(0x5122), [None, None, Some(Opcode::SaveRange(1, 2))]);
+        assert_eq!(decode(0xF000), [None, None, Some(Opcode::SetILong)]);
+        assert_eq!(
+            decode(0xF130),
+            [None, Some(Opcode::BigFont(1)), Some(Opcode::BigFont(1))]
+        );
+        assert_eq!(decode(0x8128), [None; 3]);
+        assert_eq!(decode(0xE19F), [None; 3]);
+    }
+
+    #[test]
+    fn the_mnemonics_and_the_properties() {
+        let decode = |instruction| Opcode::decode(instruction, Platform::XoChip).unwrap();
+        let mnemonics = [
+            (0x00C5, "SCD 5"),
+            (0x00FB, "SCR"),
+            (0x00FD, "EXIT"),
+            (0x5AB3, "LOAD VA, VB"),
+            (0x8AB6, "SHR VA, VB"),
+            (0xB123, "JP V0, 0x123"),
+            (0xD12F, "DRW V1, V2, 15"),
+            (0xF002, "AUDIO"),
+            (0xF33A, "PITCH V3"),
+            (0xFA55, "LD [I], VA"),
+            (0xF485, "LD V4, R"),
+        ];
+        for (instruction, mnemonic) in mnemonics {
+            assert_eq!(decode(instruction).to_string(), mnemonic);
+        }
+        assert_eq!((decode(0xF000).size(), decode(0x6000).size()), (4, 2));
+        assert_eq!(decode(0x2ABC).target(), Some(0xABC));
+        assert_eq!(decode(0xB123).target(), None);
+        assert!(decode(0xE1A1).is_skip() && !decode(0x1200).is_skip());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::launch_options::Platform;
+
+/// How FX55 and FX65 change I.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MemoryIncrement {
+    None,     // I doesn't change (SUPER-CHIP 1.1 and most modern interpreters)
+    X,        // I += X (CHIP-48 and SUPER-CHIP 1.0)
+    XPlusOne, // I += X + 1 (COSMAC VIP and XO-CHIP)
+}
+
+/// The behaviors that changed between the CHIP-8 interpreters, set at launch.
+/// The names are the ones of the Timendus quirks test.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quirks {
+    pub vf_reset: bool,          // 8XY1/8XY2/8XY3 reset VF to 0
+    pub memory: MemoryIncrement, // FX55/FX65
+    pub display_wait: bool, // DXYN waits for the next frame (vblank), so 60 sprites per second at most
+    pub clipping: bool, // the sprites are cut at the edges of the screen instead of wrapping around
+    pub shifting: bool, // 8XY6/8XYE shift VX instead of setting VX to VY shifted
+    pub jumping: bool,  // BXNN jumps to XNN + VX instead of BNNN jumping to NNN + V0
+    pub i_overflow: bool, // FX1E sets VF to 1 when I goes past the memory (Spacefight 2091!)
+    pub key_release: bool, // FX0A waits for the key to be released instead of pressed
+    pub stack_depth: u8, // nested subroutine calls before a stack overflow, 16 at most
+    pub memory_wrap: bool, // the adresses past the end of the memory wrap around instead of stopping the emulation
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        vf_reset: true,
+        memory: MemoryIncrement::XPlusOne,
+        display_wait: true,
+        clipping: true,
+        shifting: false,
+        jumping: false,
+        i_overflow: false,
+        key_release: true,
+        stack_depth: 12,
+        memory_wrap: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        vf_reset: false,
+        memory: MemoryIncrement::X,
+        display_wait: false,
+        clipping: true,
+        shifting: true,
+        jumping: true,
+        i_overflow: false,
+        key_release: false,
+        stack_depth: 16,
+        memory_wrap: false,
+    };
+
+    pub const SUPER_CHIP_1_0: Quirks = Quirks {
+        memory: MemoryIncrement::X,
+        ..Quirks::SUPER_CHIP_1_1
+    };
+
+    pub const SUPER_CHIP_1_1: Quirks = Quirks {
+        vf_reset: false,
+        memory: MemoryIncrement::None,
+        display_wait: false,
+        clipping: true,
+        shifting: true,
+        jumping: true,
+        i_overflow: false,
+        key_release: false,
+        stack_depth: 16,
+        memory_wrap: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory: MemoryIncrement::XPlusOne,
+        display_wait: false,
+        clipping: false,
+        shifting: false,
+        jumping: false,
+        i_overflow: false,
+        key_release: true,
+        stack_depth: 16,
+        memory_wrap: true,
+    };
+
+    /// What most roms written today expect.
+    pub const MODERN: Quirks = Quirks {
+        vf_reset: false,
+        memory: MemoryIncrement::None,
+        display_wait: false,
+        clipping: true,
+        shifting: true,
+        jumping: true,
+        i_overflow: true,
+        key_release: true,
+        stack_depth: 16,
+        memory_wrap: true,
+    };
+
+    pub const PRESETS: [(&'static str, Quirks); 6] = [
+        ("vip", Quirks::COSMAC_VIP),
+        ("chip48", Quirks::CHIP_48),
+        ("schip1.0", Quirks::SUPER_CHIP_1_0),
+        ("schip1.1", Quirks::SUPER_CHIP_1_1),
+        ("xochip", Quirks::XO_CHIP),
+        ("modern", Quirks::MODERN),
+    ];
+
+    /// The preset used when none is given.
+    pub fn for_platform(platform: Platform) -> Quirks {
+        match platform {
+            Platform::Chip8 => Quirks::MODERN,
+            Platform::SuperChip => Quirks::SUPER_CHIP_1_1,
+            Platform::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    pub fn preset(name: &str) -> Result<Quirks, String> {
+        let name = name.to_ascii_lowercase().replace(['-', ' ', '_'], "");
+        let name = match name.as_str() {
+            "cosmacvip" => "vip",
+            "superchip1.0" | "schip10" => "schip1.0",
+            "superchip1.1" | "superchip" | "schip" | "schip11" => "schip1.1",
+            name => name,
+        };
+        Quirks::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, quirks)| *quirks)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Quirks::PRESETS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "Unknown quirks preset: {name} (expected {})",
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Changes one quirk from a `name=value` setting, like `vf_reset=on` or `memory=x+1`.
+    pub fn set(&mut self, setting: &str) -> Result<(), String> {
+        let (name, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid quirk: {setting} (expected NAME=VALUE)"))?;
+        if name == "stack_depth" {
+            self.stack_depth = match value.parse() {
+                Ok(depth @ 1..=16) => depth,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for stack_depth: {value} (expected 1 to 16)"
+                    ))
+                }
+            };
+            return Ok(());
+        }
+        if name == "memory" {
+            self.memory = match value {
+                "none" => MemoryIncrement::None,
+                "x" => MemoryIncrement::X,
+                "x+1" => MemoryIncrement::XPlusOne,
+                _ => {
+                    return Err(format!(
+                        "Invalid value for memory: {value} (expected none, x or x+1)"
+                    ))
+                }
+            };
+            return Ok(());
+        }
+
+        let on = match value {
+            "on" | "true" | "1" => true,
+            "off" | "false" | "0" => false,
+            _ => {
+                return Err(format!(
+                    "Invalid value for {name}: {value} (expected on or off)"
+                ))
+            }
+        };
+        let quirk = match name {
+            "vf_reset" => &mut self.vf_reset,
+            "display_wait" => &mut self.display_wait,
+            "clipping" => &mut self.clipping,
+            "shifting" => &mut self.shifting,
+            "jumping" => &mut self.jumping,
+            "i_overflow" => &mut self.i_overflow,
+            "key_release" => &mut self.key_release,
+            "memory_wrap" => &mut self.memory_wrap,
+            _ => return Err(format!("Unknown quirk: {name}")),
+        };
+        *quirk = on;
+        Ok(())
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::MODERN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_found_by_name_or_alias() {
+        assert_eq!(Quirks::preset("vip"), Ok(Quirks::COSMAC_VIP));
+        assert_eq!(Quirks::preset("COSMAC-VIP"), Ok(Quirks::COSMAC_VIP));
+        assert_eq!(Quirks::preset("super-chip"), Ok(Quirks::SUPER_CHIP_1_1));
+        assert_eq!(Quirks::preset("schip1.0"), Ok(Quirks::SUPER_CHIP_1_0));
+        assert!(Quirks::preset("chip-10").is_err());
+    }
+
+    #[test]
+    fn set_changes_one_quirk() {
+        let mut quirks = Quirks::MODERN;
+        quirks.set("vf_reset=on").unwrap();
+        quirks.set("memory=x+1").unwrap();
+        quirks.set("clipping=off").unwrap();
+        assert!(quirks.vf_reset && !quirks.clipping);
+        assert_eq!(quirks.memory, MemoryIncrement::XPlusOne);
+        assert!(quirks.set("vf_reset").is_err());
+        assert!(quirks.set("wrapping=on").is_err());
+        assert!(quirks.set("memory=2").is_err());
+        quirks.set("stack_depth=12").unwrap();
+        assert_eq!(quirks.stack_depth, 12);
+        assert!(quirks.set("stack_depth=17").is_err());
+    }
+}
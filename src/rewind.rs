@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+use crate::savestate::{Layout, Snapshot};
+
+/// The last states of a `Chip8`, one per frame, to go back in time.
+/// Only the newest state is kept whole, the older ones are stored as the differences with
+/// the state after them, which are only a few bytes since a frame changes little of the machine.
+pub struct Rewind {
+    newest: Option<Vec<u8>>,   // save state bytes, in the rewind layout
+    deltas: VecDeque<Vec<u8>>, // the last one turns `newest` into the state of the frame before
+    capacity: usize,
+}
+
+impl Rewind {
+    /// Keeps up to `frames` states (60 per second).
+    pub fn new(frames: usize) -> Self {
+        Rewind {
+            newest: None,
+            deltas: VecDeque::new(),
+            capacity: frames,
+        }
+    }
+
+    /// Number of frames we can go back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Saves the current state of `chip8`, should be called after each frame.
+    pub fn record(&mut self, chip8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = chip8.snapshot().encode(Layout::Rewind);
+        if let Some(newest) = self.newest.take() {
+            if newest.len() == state.len() {
+                self.deltas.push_back(delta(&state, &newest));
+                if self.deltas.len() > self.capacity {
+                    self.deltas.pop_front();
+                }
+            } else {
+                // The memory was resized by a save state of another platform
+                self.deltas.clear();
+            }
+        }
+        self.newest = Some(state);
+    }
+
+    /// Puts `chip8` back one frame. Returns false if there is no older state.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let (Some(newest), Some(delta)) = (&mut self.newest, self.deltas.pop_back()) else {
+            return false;
+        };
+        apply_delta(newest, &delta);
+        match Snapshot::decode(newest, Layout::Rewind) {
+            Ok(snapshot) => {
+                chip8.restore(snapshot);
+                true
+            }
+            Err(_) => {
+                self.deltas.clear();
+                false
+            }
+        }
+    }
+}
+
+// DELTA COMPRESSION
+// The bytes of the two states are XORed, the result is mostly zeros so it is written as
+// (number of zeros, number of other bytes, the other bytes) repeated, the numbers as varints
+
+fn delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < from.len() {
+        let zeros = from[i..]
+            .iter()
+            .zip(&to[i..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        i += zeros;
+        let literals = from[i..]
+            .iter()
+            .zip(&to[i..])
+            .take_while(|(a, b)| a != b)
+            .count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend((i..i + literals).map(|j| from[j] ^ to[j]));
+        i += literals;
+    }
+    out
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut delta = delta.iter().copied();
+    let mut i = 0;
+    while let (Some(zeros), Some(literals)) = (read_varint(&mut delta), read_varint(&mut delta)) {
+        i += zeros;
+        for (byte, xor) in state[i..i + literals].iter_mut().zip(&mut delta) {
+            *byte ^= xor;
+        }
+        i += literals;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<usize> {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next()?;
+        n |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch_options::{LaunchOptions, Platform};
+
+    // DT = 60, then V1 += 1 in a loop : each frame changes the timer and a register
+    fn chip8(platform: Platform) -> Chip8 {
+        let mut chip8 = Chip8::new(LaunchOptions {
+            platform,
+            ..LaunchOptions::default()
+        });
+        chip8
+            .load_rom(&[0x60, 0x3C, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04])
+            .unwrap();
+        chip8
+    }
+
+    #[test]
+    fn the_deltas_give_back_the_state() {
+        let from: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+        let mut to = from.clone();
+        to[0] ^= 0xFF;
+        to[500..700].iter_mut().for_each(|byte| *byte = 0xAA);
+        to[999] = 42;
+        let mut state = to.clone();
+        apply_delta(&mut state, &delta(&from, &to));
+        assert_eq!(state, from);
+        assert_eq!(delta(&from, &from), [0xE8, 0x07, 0x00]); // 1000 zeros, no literal
+    }
+
+    #[test]
+    fn rewinding_restores_the_recorded_frames() {
+        let mut chip8 = chip8(Platform::Chip8);
+        let mut rewind = Rewind::new(100);
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            chip8.run_frame().unwrap();
+            rewind.record(&chip8);
+            states.push(chip8.snapshot().to_bytes());
+        }
+        assert_eq!(rewind.len(), 9);
+        for state in states.iter().rev().skip(1) {
+            assert!(rewind.rewind(&mut chip8));
+            assert_eq!(&chip8.snapshot().to_bytes(), state);
+        }
+        assert!(!rewind.rewind(&mut chip8));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn the_oldest_frames_are_dropped_when_full() {
+        let mut chip8 = chip8(Platform::Chip8);
+        let mut rewind = Rewind::new(3);
+        let mut states = Vec::new();
+        for _ in 0..6 {
+            chip8.run_frame().unwrap();
+            rewind.record(&chip8);
+            states.push(chip8.snapshot().to_bytes());
+        }
+        assert_eq!(rewind.len(), 3);
+        for _ in 0..3 {
+            assert!(rewind.rewind(&mut chip8));
+        }
+        assert_eq!(chip8.snapshot().to_bytes(), states[2]);
+        assert!(!rewind.rewind(&mut chip8));
+
+        let mut disabled = Rewind::new(0);
+        disabled.record(&chip8);
+        disabled.record(&chip8);
+        assert!(!disabled.rewind(&mut chip8));
+    }
+
+    #[test]
+    fn the_history_is_kept_across_the_subroutine_calls() {
+        let mut chip8 = Chip8::new(LaunchOptions::default());
+        // Call a subroutine of 6 instructions in a loop : the frames end in it or out of it
+        let mut rom = vec![0x22, 0x06, 0x71, 0x01, 0x12, 0x00];
+        rom.extend([0x70, 0x01].repeat(5));
+        rom.extend([0x00, 0xEE]);
+        chip8.load_rom(&rom).unwrap();
+        let mut rewind = Rewind::new(100);
+        let mut states = Vec::new();
+        let mut depths = Vec::new();
+        for _ in 0..20 {
+            chip8.run_frame().unwrap();
+            rewind.record(&chip8);
+            states.push(chip8.snapshot().to_bytes());
+            depths.push(chip8.stack().len());
+        }
+        assert!(depths.contains(&0) && depths.contains(&1));
+        assert_eq!(rewind.len(), 19);
+        for state in states.iter().rev().skip(1) {
+            assert!(rewind.rewind(&mut chip8));
+            assert_eq!(&chip8.snapshot().to_bytes(), state);
+        }
+    }
+
+    #[test]
+    fn a_new_memory_size_starts_over() {
+        let mut chip8 = chip8(Platform::Chip8);
+        let mut rewind = Rewind::new(10);
+        for _ in 0..3 {
+            chip8.run_frame().unwrap();
+            rewind.record(&chip8);
+        }
+        assert_eq!(rewind.len(), 2);
+
+        // A loaded save state of an XO-CHIP machine, with 64 KiB of memory
+        let mut xo_chip = self::chip8(Platform::XoChip);
+        xo_chip.run_frame().unwrap();
+        chip8.restore(xo_chip.snapshot());
+        rewind.record(&chip8);
+        assert!(rewind.is_empty());
+
+        let state = chip8.snapshot().to_bytes();
+        chip8.run_frame().unwrap();
+        rewind.record(&chip8);
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(chip8.snapshot().to_bytes(), state);
+    }
+}
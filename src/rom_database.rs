@@ -0,0 +1,313 @@
+use std::{env, fs, path::PathBuf};
+
+use crate::fonts::SmallFont;
+use crate::json::Json;
+use crate::launch_options::{parse_color, Platform};
+use crate::quirks::{MemoryIncrement, Quirks};
+
+// The programs.json file of the community chip-8-database, only with the roms we ship
+const BUNDLED_DATABASE: &str = include_str!("../data/programs.json");
+
+// The platform ids of the database that we can run, with their quirks and instructions per frame
+const PLATFORMS: [(&str, Platform, Quirks, u64); 7] = [
+    ("originalChip8", Platform::Chip8, Quirks::COSMAC_VIP, 15),
+    ("hybridVIP", Platform::Chip8, Quirks::COSMAC_VIP, 15),
+    ("modernChip8", Platform::Chip8, Quirks::MODERN, 12),
+    ("chip48", Platform::Chip8, Quirks::CHIP_48, 30),
+    (
+        "superchip1",
+        Platform::SuperChip,
+        Quirks::SUPER_CHIP_1_0,
+        30,
+    ),
+    ("superchip", Platform::SuperChip, Quirks::SUPER_CHIP_1_1, 30),
+    ("xochip", Platform::XoChip, Quirks::XO_CHIP, 100),
+];
+
+/// The CHIP-8 keys a game uses for each direction and button, from the `keys` of the database.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct GameKeys {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+/// What the database knows about a rom.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RomSettings {
+    pub title: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub ips: u64,
+    pub colors: Vec<(u8, u8, u8)>, // off, on, plane 2, both planes, the missing ones are not given
+    pub keys: [GameKeys; 2],       // of each player
+    pub font: Option<SmallFont>,
+}
+
+/// Looks the rom up in the user database (see `user_database_path`), then in the bundled one.
+/// Returns `Err` if the user database can't be read.
+pub fn identify(rom: &[u8], user_database: Option<PathBuf>) -> Result<Option<RomSettings>, String> {
+    let hash = sha1_hex(rom);
+    if let Some(path) = user_database.filter(|path| path.exists()) {
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("Could not read {} : {err}", path.display()))?;
+        let settings = lookup(&text, &hash).map_err(|err| format!("{}:{err}", path.display()))?;
+        if settings.is_some() {
+            return Ok(settings);
+        }
+    }
+    lookup(BUNDLED_DATABASE, &hash)
+}
+
+/// Finds the rom with this SHA-1 in the text of a programs.json file of the chip-8-database.
+pub fn lookup(database: &str, sha1: &str) -> Result<Option<RomSettings>, String> {
+    let programs = Json::parse(database)?;
+    for program in programs.as_array() {
+        let rom = program.get("roms").and_then(|roms| {
+            roms.members()
+                .iter()
+                .find(|(hash, _)| hash.eq_ignore_ascii_case(sha1))
+        });
+        if let Some((_, rom)) = rom {
+            let title = program.get("title").and_then(Json::as_str).unwrap_or("");
+            return Ok(rom_settings(title, rom));
+        }
+    }
+    Ok(None)
+}
+
+// None if the rom only runs on platforms we don't emulate (CHIP-8X, MegaChip...)
+fn rom_settings(title: &str, rom: &Json) -> Option<RomSettings> {
+    // The platforms are listed by order of preference
+    let (id, platform, mut quirks, tickrate) = rom
+        .get("platforms")?
+        .as_array()
+        .iter()
+        .filter_map(Json::as_str)
+        .find_map(|id| PLATFORMS.into_iter().find(|platform| platform.0 == id))?;
+
+    let quirky = rom.get("quirkyPlatforms").and_then(|quirky| quirky.get(id));
+    if let Some(quirky) = quirky {
+        apply_quirks(&mut quirks, quirky);
+    }
+
+    let colors = rom
+        .get("colors")
+        .and_then(|colors| colors.get("pixels"))
+        .map(|pixels| {
+            pixels
+                .as_array()
+                .iter()
+                .filter_map(|color| parse_color(color.as_str()?).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // "up", "a"... for the player 1, "player2Up", "player2A"... for the player 2
+    let keys = rom.get("keys");
+    let key = |name: &str| {
+        let key = keys?.get(name)?.as_f64()?;
+        (0.0..16.0).contains(&key).then_some(key as u8)
+    };
+    let player_keys = |prefix: &str| {
+        let key = |name: &str| match prefix {
+            "" => key(name),
+            _ => key(&format!(
+                "{prefix}{}{}",
+                name[..1].to_uppercase(),
+                &name[1..]
+            )),
+        };
+        GameKeys {
+            up: key("up"),
+            down: key("down"),
+            left: key("left"),
+            right: key("right"),
+            a: key("a"),
+            b: key("b"),
+        }
+    };
+
+    Some(RomSettings {
+        title: title.to_string(),
+        platform,
+        quirks,
+        ips: rom
+            .get("tickrate")
+            .and_then(Json::as_f64)
+            .filter(|tickrate| *tickrate >= 1.0)
+            .map_or(tickrate, |tickrate| tickrate as u64)
+            * 60,
+        colors,
+        keys: [player_keys(""), player_keys("player2")],
+        font: rom
+            .get("fontStyle")
+            .and_then(Json::as_str)
+            .and_then(|style| SmallFont::parse(style).ok()),
+    })
+}
+
+// The quirks of the database, true when the rom needs the behavior
+fn apply_quirks(quirks: &mut Quirks, quirky: &Json) {
+    let quirk = |name| quirky.get(name).and_then(Json::as_bool);
+    if let Some(shift) = quirk("shift") {
+        quirks.shifting = shift;
+    }
+    let increment_by_x = quirk("memoryIncrementByX").unwrap_or(quirks.memory == MemoryIncrement::X);
+    let leave_i = quirk("memoryLeaveIUnchanged").unwrap_or(quirks.memory == MemoryIncrement::None);
+    quirks.memory = if leave_i {
+        MemoryIncrement::None
+    } else if increment_by_x {
+        MemoryIncrement::X
+    } else {
+        MemoryIncrement::XPlusOne
+    };
+    if let Some(wrap) = quirk("wrap") {
+        quirks.clipping = !wrap;
+    }
+    if let Some(jump) = quirk("jump") {
+        quirks.jumping = jump;
+    }
+    if let Some(vblank) = quirk("vblank") {
+        quirks.display_wait = vblank;
+    }
+    if let Some(logic) = quirk("logic") {
+        quirks.vf_reset = logic;
+    }
+}
+
+/// Where the user adds or overrides roms, in the same format as the bundled database :
+/// `$XDG_CONFIG_HOME/chip/programs.json`, `~/.config/chip/programs.json` or `%APPDATA%\chip\programs.json`.
+pub fn user_database_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config.join("chip").join("programs.json"))
+}
+
+// SHA-1
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // Padded with a 1 bit, zeros and the length in bits to a multiple of 64 bytes
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (h, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(value);
+        }
+    }
+
+    let mut hash = [0; 20];
+    for (bytes, h) in hash.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&h.to_be_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_matches_the_reference() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            sha1_hex(&[b'a'; 1000]),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+
+    #[test]
+    fn lookup_reads_the_platform_quirks_tickrate_colors_and_keys() {
+        let database = r##"[{
+            "title": "Game",
+            "roms": {"ABCDEF": {
+                "platforms": ["megachip8", "chip48", "originalChip8"],
+                "quirkyPlatforms": {"chip48": {"memoryIncrementByX": false, "wrap": true}},
+                "tickrate": 20,
+                "colors": {"pixels": ["#000000", "#ff8000"]},
+                "keys": {"left": 4, "right": 6, "a": 15, "player2Up": 12},
+                "fontStyle": "dream6800"
+            }}
+        }]"##;
+        let settings = lookup(database, "abcdef").unwrap().unwrap();
+        assert_eq!(settings.title, "Game");
+        assert_eq!(settings.platform, Platform::Chip8);
+        assert_eq!(
+            settings.quirks,
+            Quirks {
+                memory: MemoryIncrement::XPlusOne,
+                clipping: false,
+                ..Quirks::CHIP_48
+            }
+        );
+        assert_eq!(settings.ips, 1200);
+        assert_eq!(settings.colors, [(0, 0, 0), (0xFF, 0x80, 0)]);
+        let [player_1, player_2] = settings.keys;
+        assert_eq!(
+            (player_1.left, player_1.a, player_1.up),
+            (Some(4), Some(15), None)
+        );
+        assert_eq!((player_2.up, player_2.left), (Some(12), None));
+        assert_eq!(settings.font, Some(SmallFont::Dream6800));
+        assert_eq!(lookup(database, "123456"), Ok(None));
+    }
+
+    #[test]
+    fn the_bundled_database_identifies_the_roms() {
+        let rom = fs::read("roms/games/Tetris [Fran Dachille, 1991].ch8").unwrap();
+        let settings = identify(&rom, None).unwrap().unwrap();
+        assert_eq!(settings.title, "Tetris");
+        assert_eq!((settings.quirks, settings.ips), (Quirks::CHIP_48, 1800));
+
+        let rom = fs::read("roms/hires/Hires Test [Tom Swan, 1979].ch8").unwrap();
+        assert_eq!(
+            identify(&rom, None).unwrap().unwrap().quirks,
+            Quirks::COSMAC_VIP
+        );
+        assert_eq!(identify(&[0x12, 0x00], None), Ok(None));
+    }
+}
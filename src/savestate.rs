@@ -0,0 +1,375 @@
+use crate::constants::MAX_STACK_DEPTH;
+use crate::launch_options::Platform;
+use crate::memory::Memory;
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::screen::Screen;
+use crate::stack::Stack;
+
+/// Version of the save state files, increased when the format changes.
+/// The older versions that can still be read are handled in `Snapshot::from_bytes`.
+pub const SAVE_STATE_VERSION: u16 = 5;
+const MAGIC: &[u8; 8] = b"CHIP8SAV";
+
+/// The whole state of a `Chip8` (see `Chip8::snapshot`), except the keys that are held.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) pc: u16,
+    pub(crate) stack: Stack,
+    pub(crate) memory: Memory,
+    pub(crate) screen: Screen,
+    pub(crate) platform: Platform,
+    pub(crate) ips: u64,
+    pub(crate) quirks: Quirks,
+    pub(crate) cycles: u64,
+    pub(crate) waited: u64,
+    pub(crate) frames: u64,
+    pub(crate) in_frame: bool,
+    pub(crate) exited: bool,
+}
+
+/// How the bytes of a `Snapshot` are laid out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Layout {
+    File,
+    // For `Rewind` : the whole stack and a screen padded to `HIRES`, so that the size only
+    // depends on the memory size and two states can be compared byte by byte
+    Rewind,
+}
+
+// Format (big endian) :
+// "CHIP8SAV", version (u16)
+// platform (u8), ips (u64), quirks : vf_reset, memory (0 : none, 1 : x, 2 : x+1), display_wait, clipping,
+//   shifting, jumping, i_overflow, key_release (u8 each), stack_depth (u8, since version 4),
+//   memory_wrap (u8, since version 5)
+//   before version 3 : 8XY6, BNNN, FX1E, FX55 and since version 2 logic behaviors (u8, 0 : new, 1 : old)
+// pc (u16), stack size (u8) and adresses (u16), cycles (u64), waited (u64, since version 3), frames (u64),
+//   in_frame (u8), exited (u8)
+// memory : size (u32) and bytes, V0-VF, I (u16), delay timer, sound timer, RPL flags (16), audio pattern (16), pitch
+// screen : width (u16), height (u16), selected planes (u8), planes of each pixel (u8) row by row
+// The rewind layout writes the 16 adresses of the stack, 0 past its size, and pads the screen with 0
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(Layout::File)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, String> {
+        Snapshot::decode(bytes, Layout::File)
+    }
+
+    pub(crate) fn encode(&self, layout: Layout) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(SAVE_STATE_VERSION.to_be_bytes());
+
+        out.push(match self.platform {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        });
+        out.extend(self.ips.to_be_bytes());
+        let quirks = self.quirks;
+        out.extend([
+            quirks.vf_reset as u8,
+            match quirks.memory {
+                MemoryIncrement::None => 0,
+                MemoryIncrement::X => 1,
+                MemoryIncrement::XPlusOne => 2,
+            },
+            quirks.display_wait as u8,
+            quirks.clipping as u8,
+            quirks.shifting as u8,
+            quirks.jumping as u8,
+            quirks.i_overflow as u8,
+            quirks.key_release as u8,
+            quirks.stack_depth,
+            quirks.memory_wrap as u8,
+        ]);
+
+        out.extend(self.pc.to_be_bytes());
+        out.push(self.stack.len() as u8);
+        for adress in self.stack.adresses() {
+            out.extend(adress.to_be_bytes());
+        }
+        if layout == Layout::Rewind {
+            out.resize(out.len() + 2 * (MAX_STACK_DEPTH - self.stack.len()), 0);
+        }
+        out.extend(self.cycles.to_be_bytes());
+        out.extend(self.waited.to_be_bytes());
+        out.extend(self.frames.to_be_bytes());
+        out.push(self.in_frame as u8);
+        out.push(self.exited as u8);
+
+        self.memory.save(&mut out);
+        self.screen.save(&mut out, layout == Layout::Rewind);
+        out
+    }
+
+    pub(crate) fn decode(bytes: &[u8], layout: Layout) -> Result<Snapshot, String> {
+        let mut reader = Reader { bytes };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err("Not a save state".to_string());
+        }
+        let version = reader.u16()?;
+        if version > SAVE_STATE_VERSION {
+            return Err(format!(
+                "Save state version {version} is newer than this emulator (version {SAVE_STATE_VERSION})"
+            ));
+        }
+
+        let platform = match reader.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            platform => return Err(format!("Unknown platform in save state: {platform}")),
+        };
+        let ips = reader.u64()?;
+        let quirks = if version < 3 {
+            read_old_behaviors(&mut reader, version)?
+        } else {
+            Quirks {
+                vf_reset: reader.u8()? != 0,
+                memory: match reader.u8()? {
+                    0 => MemoryIncrement::None,
+                    1 => MemoryIncrement::X,
+                    2 => MemoryIncrement::XPlusOne,
+                    memory => return Err(format!("Unknown memory quirk in save state: {memory}")),
+                },
+                display_wait: reader.u8()? != 0,
+                clipping: reader.u8()? != 0,
+                shifting: reader.u8()? != 0,
+                jumping: reader.u8()? != 0,
+                i_overflow: reader.u8()? != 0,
+                key_release: reader.u8()? != 0,
+                stack_depth: if version < 4 {
+                    16
+                } else {
+                    read_stack_depth(&mut reader)?
+                },
+                memory_wrap: version < 5 || reader.u8()? != 0,
+            }
+        };
+
+        let pc = reader.u16()?;
+        let stack_size = reader.u8()?;
+        let mut stack = Stack::new(quirks.stack_depth as usize);
+        for _ in 0..stack_size {
+            if !stack.push(reader.u16()?) {
+                return Err(format!("Stack deeper than {} in save state", stack.depth()));
+            }
+        }
+        if layout == Layout::Rewind {
+            reader.bytes(2 * (MAX_STACK_DEPTH - stack.len()))?;
+        }
+        let snapshot = Snapshot {
+            pc,
+            stack,
+            platform,
+            ips,
+            quirks,
+            cycles: reader.u64()?,
+            waited: if version < 3 { 0 } else { reader.u64()? },
+            frames: reader.u64()?,
+            in_frame: reader.u8()? != 0,
+            exited: reader.u8()? != 0,
+            memory: Memory::load(&mut reader)?,
+            screen: Screen::load(&mut reader, layout == Layout::Rewind)?,
+        };
+        if !reader.bytes.is_empty() {
+            return Err("Unexpected data at the end of the save state".to_string());
+        }
+        Ok(snapshot)
+    }
+}
+
+// 1 to 16 like --quirk stack_depth, the stack would silently use another depth
+fn read_stack_depth(reader: &mut Reader) -> Result<u8, String> {
+    match reader.u8()? {
+        depth @ 1..=16 => Ok(depth),
+        depth => Err(format!("Invalid stack depth in save state: {depth}")),
+    }
+}
+
+// The quirks were compatibility behaviors set to new or old before version 3
+fn read_old_behaviors(reader: &mut Reader, version: u16) -> Result<Quirks, String> {
+    let mut old = [false; 5]; // 8XY6, BNNN, FX1E, FX55, logic
+    let count = if version < 2 { 4 } else { 5 };
+    for behavior in old.iter_mut().take(count) {
+        *behavior = reader.u8()? != 0;
+    }
+    Ok(Quirks {
+        vf_reset: old[4],
+        memory: if old[3] {
+            MemoryIncrement::XPlusOne
+        } else {
+            MemoryIncrement::None
+        },
+        display_wait: false,
+        clipping: true,
+        shifting: !old[0],
+        jumping: !old[1],
+        i_overflow: !old[2],
+        key_release: false,
+        stack_depth: 16,
+        memory_wrap: true,
+    })
+}
+
+/// Reads the values written in a save state one after the other.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < n {
+            return Err("Truncated save state".to_string());
+        }
+        let (bytes, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch_options::LaunchOptions;
+    use crate::Chip8;
+
+    // Offsets in the version 5 format
+    const QUIRKS: usize = 19; // 10 bytes, stack_depth at 27
+    const WAITED: usize = 40; // after pc, an empty stack and cycles
+
+    // The snapshot of a machine that ran a few instructions, with `calls` nested subroutine calls
+    fn snapshot(calls: usize) -> Snapshot {
+        let mut chip8 = Chip8::new(LaunchOptions::default());
+        // V0 = 5, DT = V0, then 2NNN to the next instruction `calls` times and loop
+        let mut rom = vec![0x60, 0x05, 0xF0, 0x15];
+        for i in 0..calls {
+            rom.extend((0x2206 + 2 * i as u16).to_be_bytes());
+        }
+        rom.extend((0x1204 + 2 * calls as u16).to_be_bytes());
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..calls + 3 {
+            chip8.step().unwrap();
+        }
+        chip8.snapshot()
+    }
+
+    // The bytes of an older version, made from the ones of the current one without a stack
+    fn old_version(version: u16, quirks: &[u8]) -> Vec<u8> {
+        let bytes = snapshot(0).to_bytes();
+        let mut old = bytes[..QUIRKS].to_vec();
+        old[8..10].copy_from_slice(&version.to_be_bytes());
+        old.extend(quirks);
+        old.extend(&bytes[QUIRKS + 10..WAITED]);
+        let rest = if version < 3 { WAITED + 8 } else { WAITED };
+        old.extend(&bytes[rest..]);
+        old
+    }
+
+    #[test]
+    fn a_snapshot_gives_back_the_same_bytes() {
+        let snapshot = snapshot(2);
+        assert_eq!(snapshot.stack.adresses(), [0x206, 0x208]);
+        let bytes = snapshot.to_bytes();
+        let decoded = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!((decoded.pc, decoded.cycles), (0x208, 5));
+        assert_eq!(decoded.memory.read_delay_timer(), 5);
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn the_older_versions_are_read_with_the_defaults() {
+        let bytes = snapshot(0).to_bytes();
+        let current = &bytes[QUIRKS..QUIRKS + 10];
+
+        let v4 = Snapshot::from_bytes(&old_version(4, &current[..9])).unwrap();
+        assert_eq!(v4.quirks, Quirks::MODERN);
+        let v3 = Snapshot::from_bytes(&old_version(3, &current[..8])).unwrap();
+        assert_eq!((v3.quirks.stack_depth, v3.quirks.memory_wrap), (16, true));
+
+        // 8XY6 old, BNNN new, FX1E old, FX55 old, logic old
+        let v2 = Snapshot::from_bytes(&old_version(2, &[1, 0, 1, 1, 1])).unwrap();
+        assert_eq!(
+            v2.quirks,
+            Quirks {
+                vf_reset: true,
+                memory: MemoryIncrement::XPlusOne,
+                display_wait: false,
+                clipping: true,
+                shifting: false,
+                jumping: true,
+                i_overflow: false,
+                key_release: false,
+                stack_depth: 16,
+                memory_wrap: true,
+            }
+        );
+        assert_eq!((v2.waited, v2.cycles, v2.pc), (0, 3, 0x204));
+        let v1 = Snapshot::from_bytes(&old_version(1, &[0, 1, 0, 0])).unwrap();
+        assert!(!v1.quirks.vf_reset && !v1.quirks.jumping && v1.quirks.shifting);
+        assert_eq!(v1.memory.read_register(0), 5);
+    }
+
+    #[test]
+    fn the_invalid_save_states_are_rejected() {
+        let bytes = snapshot(2).to_bytes();
+        let error = |change: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            change(&mut bytes);
+            Snapshot::from_bytes(&bytes).err().unwrap()
+        };
+        assert_eq!(error(&|bytes| bytes[0] = b'X'), "Not a save state");
+        assert_eq!(
+            error(&|bytes| bytes[9] = 9),
+            "Save state version 9 is newer than this emulator (version 5)"
+        );
+        assert_eq!(
+            error(&|bytes| bytes[10] = 7),
+            "Unknown platform in save state: 7"
+        );
+        assert_eq!(
+            error(&|bytes| bytes.push(0)),
+            "Unexpected data at the end of the save state"
+        );
+        assert_eq!(error(&|bytes| bytes.truncate(100)), "Truncated save state");
+        assert_eq!(
+            error(&|bytes| bytes[QUIRKS + 8] = 1),
+            "Stack deeper than 1 in save state"
+        );
+        assert_eq!(
+            error(&|bytes| bytes[QUIRKS + 8] = 0),
+            "Invalid stack depth in save state: 0"
+        );
+        assert_eq!(
+            error(&|bytes| bytes[QUIRKS + 8] = 17),
+            "Invalid stack depth in save state: 17"
+        );
+        assert_eq!(
+            error(&|bytes| *bytes.last_mut().unwrap() = 4),
+            "Invalid planes in save state: 4"
+        );
+    }
+}
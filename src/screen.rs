@@ -1,61 +1,207 @@
-use crate::launch_options::*;
+use crate::savestate::Reader;
+
+pub const LORES: (usize, usize) = (64, 32); // width, height
+pub const HIRES: (usize, usize) = (128, 64); // SUPER-CHIP only
+pub const VIP_HIRES: (usize, usize) = (64, 64); // 2-page hires of the COSMAC VIP (roms/hires)
 
 #[derive(Copy, Clone)]
 pub struct Pixel {
-    state: bool,
+    planes: u8, // bit 0 : plane 1, bit 1 : plane 2 (XO-CHIP only)
 }
 
+const OFF: Pixel = Pixel { planes: 0 };
+
+#[derive(Clone)]
 pub struct Screen {
-    pub pixels: [[Pixel; 64]; 32],
+    pixels: Vec<Pixel>, // row by row
+    width: usize,
+    height: usize,
+    planes: u8,  // planes selected by XO-CHIP FN01, the other platforms only use plane 1
+    dirty: bool, // the screen changed since it was last displayed
 }
 
 impl Screen {
     // Static methods
     pub fn new() -> Screen {
         Screen {
-            pixels: [[Pixel { state: false }; 64]; 32],
+            pixels: vec![OFF; LORES.0 * LORES.1],
+            width: LORES.0,
+            height: LORES.1,
+            planes: 1,
+            dirty: true,
         }
     }
 
-    pub fn iter_coords() -> impl Iterator<Item = (u8, u8)> {
-        (0..32).flat_map(|x| (0..64).map(move |y| (y, x)))
+    // Methods
+    pub fn iter_coords(&self) -> impl Iterator<Item = (u8, u8)> {
+        let (width, height) = (self.width, self.height);
+        (0..height).flat_map(move |y| (0..width).map(move |x| (x as u8, y as u8)))
     }
 
-    // Methods
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Resizes the screen (`LORES`, `HIRES` or `VIP_HIRES`), it is cleared.
+    pub fn set_resolution(&mut self, (width, height): (usize, usize)) {
+        (self.width, self.height) = (width, height);
+        self.pixels = vec![OFF; self.width * self.height];
+        self.dirty = true;
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    /// Turns off the selected planes.
     pub fn clear(&mut self) {
-        for x in 0..32 {
-            for y in 0..64 {
-                self.pixels[x][y].state = false;
-            }
+        for pixel in self.pixels.iter_mut() {
+            pixel.planes &= !self.planes;
         }
+        self.dirty = true;
+    }
+
+    /// The planes that are on at (x, y) : 0 is off, 1 is plane 1, 2 is plane 2, 3 is both.
+    pub fn pixel(&self, x: u8, y: u8) -> u8 {
+        self.pixels[y as usize * self.width + x as usize].planes
     }
 
     pub fn is_on(&self, x: u8, y: u8) -> bool {
-        self.pixels[y as usize][x as usize].state
+        self.pixel(x, y) != 0
+    }
+
+    /// Flips the pixel (x, y) of `plane` (1 or 2), returns true if it was on (collision).
+    pub fn flip(&mut self, x: u8, y: u8, plane: u8) -> bool {
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        let was_on = pixel.planes & plane != 0;
+        pixel.planes ^= plane;
+        self.dirty = true;
+        was_on
+    }
+
+    // SCROLLING (SUPER-CHIP and XO-CHIP)
+    // Only the selected planes move, the pixels going out of the screen are lost and the new ones are off
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
     }
 
-    pub fn set(&mut self, x: u8, y: u8, state: bool) {
-        let x = x as usize;
-        let y = y as usize;
-        self.pixels[y][x].state = state;
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let old = self.pixels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (old_x, old_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&old_x) && (0..height).contains(&old_y) {
+                    old[(old_y * width + old_x) as usize].planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                pixel.planes = (pixel.planes & !self.planes) | (moved & self.planes);
+            }
+        }
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    // SAVE STATES (format in savestate.rs)
+
+    /// `padded` writes `HIRES` pixels whatever the resolution, the ones past the screen are 0.
+    pub(crate) fn save(&self, out: &mut Vec<u8>, padded: bool) {
+        out.extend((self.width as u16).to_be_bytes());
+        out.extend((self.height as u16).to_be_bytes());
+        out.push(self.planes);
+        out.extend(self.pixels.iter().map(|pixel| pixel.planes));
+        if padded {
+            out.resize(out.len() + HIRES.0 * HIRES.1 - self.pixels.len(), 0);
+        }
+    }
+
+    pub(crate) fn load(reader: &mut Reader, padded: bool) -> Result<Screen, String> {
+        let resolution = (reader.u16()? as usize, reader.u16()? as usize);
+        if ![LORES, HIRES, VIP_HIRES].contains(&resolution) {
+            return Err(format!(
+                "Invalid resolution in save state: {}x{}",
+                resolution.0, resolution.1
+            ));
+        }
+        // 2 planes at most, the display indexes the colors with them
+        let planes = reader.u8()?;
+        let pixels = reader.bytes(resolution.0 * resolution.1)?;
+        if let Some(invalid) = std::iter::once(&planes).chain(pixels).find(|&&p| p > 0b11) {
+            return Err(format!("Invalid planes in save state: {invalid}"));
+        }
+        if padded {
+            reader.bytes(HIRES.0 * HIRES.1 - pixels.len())?;
+        }
+        Ok(Screen {
+            pixels: pixels.iter().map(|&planes| Pixel { planes }).collect(),
+            width: resolution.0,
+            height: resolution.1,
+            planes,
+            dirty: true,
+        })
     }
 
     // DEBUG
 
+    /// One line of text per row, `█` for the pixels that are on.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.pixels.chunks(self.width) {
+            text.extend(
+                row.iter()
+                    .map(|pixel| if pixel.planes != 0 { '█' } else { ' ' }),
+            );
+            text.push('\n');
+        }
+        text
+    }
+
     #[allow(dead_code)]
-    pub fn debug_display(&self) {
-        if !DEBUG {
+    pub fn debug_display(&self, debug: bool) {
+        // Don't erase the instructions printed in debug mode
+        if !debug {
             print!("\x1B[2J\x1B[1;1H");
         }
-        for x in 0..32 {
-            for y in 0..64 {
-                if self.pixels[x][y].state {
-                    print!("█");
-                } else {
-                    print!(" ");
-                }
-            }
-            println!();
-        }
+        print!("{}", self.to_text());
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen::new()
     }
 }
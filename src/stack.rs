@@ -0,0 +1,54 @@
+use crate::constants::MAX_STACK_DEPTH;
+
+/// The return adresses of the subroutines being executed, at most `depth` of them
+/// (12 on the COSMAC VIP, 16 on SUPER-CHIP).
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stack {
+    adresses: [u16; MAX_STACK_DEPTH],
+    len: usize,
+    depth: usize,
+}
+
+impl Stack {
+    /// An empty stack, `depth` is clamped to 1..=`MAX_STACK_DEPTH`.
+    pub fn new(depth: usize) -> Stack {
+        Stack {
+            adresses: [0; MAX_STACK_DEPTH],
+            len: 0,
+            depth: depth.clamp(1, MAX_STACK_DEPTH),
+        }
+    }
+
+    /// Returns false if the stack is full, it is then unchanged.
+    #[must_use]
+    pub fn push(&mut self, adress: u16) -> bool {
+        if self.len == self.depth {
+            return false;
+        }
+        self.adresses[self.len] = adress;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u16> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.adresses[self.len])
+    }
+
+    /// From the first call to the last one.
+    pub fn adresses(&self) -> &[u16] {
+        &self.adresses[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
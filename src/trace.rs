@@ -0,0 +1,335 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+
+// The instructions executed, with what they changed, for the debugger or a file (--trace)
+
+pub const DEFAULT_BUFFER: usize = 1000;
+
+const TEXT_HEADER: &str =
+    "   cycle | adr    | op   | mnemonic         | I, timers              | registers";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TraceFormat {
+    Text,
+    JsonLines, // one JSON object per instruction
+}
+
+impl TraceFormat {
+    pub fn parse(format: &str) -> Result<TraceFormat, String> {
+        match format.to_ascii_lowercase().as_str() {
+            "text" => Ok(TraceFormat::Text),
+            "json" | "jsonl" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!(
+                "Invalid trace format: {format} (expected text or json)"
+            )),
+        }
+    }
+}
+
+/// The instructions to trace : their adress is from `start` to `end` (included)
+/// and their first hexadecimal digit is in `classes` (bit N for the instructions NXXX).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TraceFilter {
+    pub start: u16,
+    pub end: u16,
+    pub classes: u16,
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        TraceFilter {
+            start: 0,
+            end: 0xFFFF,
+            classes: 0xFFFF,
+        }
+    }
+}
+
+impl TraceFilter {
+    pub fn accepts(&self, pc: u16, instruction: u16) -> bool {
+        (self.start..=self.end).contains(&pc) && self.classes & (1 << (instruction >> 12)) != 0
+    }
+
+    /// Parses `200-2FF` or a single adress `2A4`, in hexadecimal.
+    pub fn parse_range(&mut self, range: &str) -> Result<(), String> {
+        let invalid =
+            || format!("Invalid adress range: {range} (expected START-END in hexadecimal)");
+        let parse = |adress: &str| {
+            u16::from_str_radix(adress.trim().trim_start_matches("0x"), 16).map_err(|_| invalid())
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(range)?, parse(range)?),
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        (self.start, self.end) = (start, end);
+        Ok(())
+    }
+
+    /// Parses the first hexadecimal digits of the instructions, separated by commas : `8,D,F`.
+    pub fn parse_classes(&mut self, classes: &str) -> Result<(), String> {
+        self.classes = 0;
+        for class in classes.split(',') {
+            let digit = match class
+                .trim()
+                .trim_end_matches(['X', 'x', 'Y', 'y', 'N', 'n'])
+            {
+                digit if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+                _ => None,
+            };
+            let Some(digit) = digit else {
+                return Err(format!(
+                    "Invalid instruction class: {class} (expected a hexadecimal digit, like 8 or D)"
+                ));
+            };
+            self.classes |= 1 << digit;
+        }
+        Ok(())
+    }
+}
+
+/// How to trace, from the command line.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceSettings {
+    pub file: Option<String>, // - for the standard output
+    pub buffer: usize,        // number of instructions kept for the debugger
+    pub format: TraceFormat,
+    pub filter: TraceFilter,
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        TraceSettings {
+            file: None,
+            buffer: DEFAULT_BUFFER,
+            format: TraceFormat::Text,
+            filter: TraceFilter::default(),
+        }
+    }
+}
+
+/// One executed instruction.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceEntry {
+    pub cycle: u64, // number of instructions executed before
+    pub pc: u16,
+    pub instruction: u16,
+    pub mnemonic: String,
+    pub registers: Vec<(u8, u8, u8)>, // the registers it changed : index, before, after
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8} | 0x{:04X} | {:04X} | {:<16} | I 0x{:04X} DT {:>3} ST {:>3} |",
+            self.cycle,
+            self.pc,
+            self.instruction,
+            self.mnemonic,
+            self.i,
+            self.delay_timer,
+            self.sound_timer
+        )?;
+        for (register, before, after) in &self.registers {
+            write!(f, " V{register:X} {before:02X}->{after:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TraceEntry {
+    /// `{"cycle":0,"pc":512,"opcode":"6012","mnemonic":"LD V0, 0x12","registers":{"V0":[0,18]},"i":0,"dt":0,"st":0}`
+    pub fn to_json(&self) -> String {
+        // The mnemonics don't have any character to escape
+        let mut json = format!(
+            r#"{{"cycle":{},"pc":{},"opcode":"{:04X}","mnemonic":"{}","registers":{{"#,
+            self.cycle, self.pc, self.instruction, self.mnemonic
+        );
+        for (n, (register, before, after)) in self.registers.iter().enumerate() {
+            let separator = if n == 0 { "" } else { "," };
+            let _ = write!(json, r#"{separator}"V{register:X}":[{before},{after}]"#);
+        }
+        let _ = write!(
+            json,
+            r#"}},"i":{},"dt":{},"st":{}}}"#,
+            self.i, self.delay_timer, self.sound_timer
+        );
+        json
+    }
+}
+
+/// Records the instructions that pass the filter : the last ones in memory, and all of them
+/// in the output file if there is one.
+pub struct Trace {
+    filter: TraceFilter,
+    format: TraceFormat,
+    capacity: usize,
+    entries: VecDeque<TraceEntry>,
+    output: Option<Box<dyn Write>>,
+}
+
+impl Trace {
+    /// Returns `Err` if the output file can't be created.
+    pub fn new(settings: &TraceSettings) -> Result<Trace, String> {
+        let output: Option<Box<dyn Write>> = match settings.file.as_deref() {
+            None => None,
+            Some("-") => Some(Box::new(io::stdout())),
+            Some(path) => {
+                let file =
+                    File::create(path).map_err(|err| format!("Could not create {path} : {err}"))?;
+                Some(Box::new(LineWriter::new(file)))
+            }
+        };
+        let mut trace = Trace {
+            filter: settings.filter,
+            format: settings.format,
+            capacity: settings.buffer,
+            entries: VecDeque::with_capacity(settings.buffer),
+            output,
+        };
+        if settings.format == TraceFormat::Text {
+            trace.write(TEXT_HEADER);
+        }
+        Ok(trace)
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.output.is_some() {
+            let line = match self.format {
+                TraceFormat::Text => entry.to_string(),
+                TraceFormat::JsonLines => entry.to_json(),
+            };
+            self.write(&line);
+        }
+        if self.capacity > 0 {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+        }
+    }
+
+    /// The last instructions recorded, the oldest first.
+    pub fn entries(&self) -> &VecDeque<TraceEntry> {
+        &self.entries
+    }
+
+    /// The last `count` instructions recorded in text, with the header.
+    pub fn last_to_text(&self, count: usize) -> String {
+        let mut text = TEXT_HEADER.to_string();
+        for entry in self
+            .entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(count))
+        {
+            let _ = write!(text, "\n{entry}");
+        }
+        text
+    }
+
+    // The emulation continues without the file if it can't be written anymore
+    fn write(&mut self, line: &str) {
+        if let Some(output) = &mut self.output {
+            if let Err(err) = writeln!(output, "{line}") {
+                eprintln!("Could not write the trace : {err}");
+                self.output = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch_options::LaunchOptions;
+    use crate::Chip8;
+
+    #[test]
+    fn the_filter_checks_the_adress_and_the_class() {
+        let mut filter = TraceFilter::default();
+        assert!(filter.accepts(0xFFFE, 0xF000));
+        filter.parse_range("200-2ff").unwrap();
+        filter.parse_classes("8,DXYN").unwrap();
+        assert!(filter.accepts(0x200, 0x8124));
+        assert!(filter.accepts(0x2FF, 0xD015));
+        assert!(!filter.accepts(0x300, 0x8124));
+        assert!(!filter.accepts(0x200, 0x6012));
+        assert!(filter.parse_range("300-200").is_err());
+        assert!(filter.parse_classes("8,G").is_err());
+    }
+
+    #[test]
+    fn the_entries_are_written_in_text_and_json() {
+        let entry = TraceEntry {
+            cycle: 3,
+            pc: 0x206,
+            instruction: 0x8014,
+            mnemonic: "ADD V0, V1".to_string(),
+            registers: vec![(0, 0xFF, 0x01), (0xF, 0, 1)],
+            i: 0x300,
+            delay_timer: 60,
+            sound_timer: 0,
+        };
+        assert_eq!(
+            entry.to_string(),
+            "       3 | 0x0206 | 8014 | ADD V0, V1       | I 0x0300 DT  60 ST   0 | V0 FF->01 VF 00->01"
+        );
+        assert_eq!(
+            entry.to_json(),
+            r#"{"cycle":3,"pc":518,"opcode":"8014","mnemonic":"ADD V0, V1","registers":{"V0":[255,1],"VF":[0,1]},"i":768,"dt":60,"st":0}"#
+        );
+    }
+
+    #[test]
+    fn the_chip8_records_the_filtered_instructions_in_the_buffer() {
+        let mut chip8 = Chip8::new(LaunchOptions::default());
+        // V0 = 0x12, I = 0x300, V0 += 1, jump to itself
+        chip8
+            .load_rom(&[0x60, 0x12, 0xA3, 0x00, 0x70, 0x01, 0x12, 0x06])
+            .unwrap();
+        let mut settings = TraceSettings {
+            buffer: 2,
+            ..TraceSettings::default()
+        };
+        settings.filter.parse_classes("6,7,A").unwrap();
+        chip8.set_trace(Some(Trace::new(&settings).unwrap()));
+        for _ in 0..5 {
+            chip8.step().unwrap();
+        }
+
+        let entries = chip8.trace().unwrap().entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].cycle, entries[0].pc), (1, 0x202));
+        assert_eq!(entries[0].mnemonic, "LD I, 0x300");
+        assert_eq!((entries[1].cycle, entries[1].i), (2, 0x300));
+        assert_eq!(entries[1].registers, [(0, 0x12, 0x13)]);
+    }
+
+    #[test]
+    fn bxnn_is_traced_with_the_register_of_the_jumping_quirk() {
+        let mut options = LaunchOptions::default();
+        let mnemonic = |options: &LaunchOptions| {
+            let mut chip8 = Chip8::new(options.clone());
+            chip8.load_rom(&[0xB3, 0x04]).unwrap();
+            chip8.set_trace(Some(Trace::new(&TraceSettings::default()).unwrap()));
+            chip8.step().unwrap();
+            chip8.trace().unwrap().entries()[0].mnemonic.clone()
+        };
+        options.quirks.jumping = true;
+        assert_eq!(mnemonic(&options), "JP V3, 0x304");
+        options.quirks.jumping = false;
+        assert_eq!(mnemonic(&options), "JP V0, 0x304");
+    }
+}
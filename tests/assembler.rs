@@ -0,0 +1,41 @@
+// Disassembles every rom of roms/ on each platform and assembles it back, the bytes must not change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip::assembler;
+use chip::disassembler;
+use chip::launch_options::Platform;
+
+// The roms, without the text files that come with some of them
+fn roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            self::roms(&path, roms);
+        } else if path.extension().is_none_or(|extension| extension != "txt") {
+            roms.push(path);
+        }
+    }
+}
+
+#[test]
+fn the_disassembled_roms_assemble_to_the_same_bytes() {
+    let mut paths = Vec::new();
+    roms(Path::new("roms"), &mut paths);
+    assert!(paths.len() >= 100);
+
+    for path in paths {
+        let rom = fs::read(&path).unwrap();
+        for platform in [Platform::Chip8, Platform::SuperChip, Platform::XoChip] {
+            let source = disassembler::disassemble(&rom, platform);
+            let assembled = assembler::assemble(&source, &path)
+                .unwrap_or_else(|err| panic!("{} on {platform:?}: {err}", path.display()));
+            assert!(
+                assembled == rom,
+                "{} on {platform:?} doesn't give back the same bytes",
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Runs the test roms headlessly and compares the screen with the one stored in tests/golden.
+// UPDATE_GOLDEN=1 cargo test --test conformance writes the current screens instead.
+
+use std::{env, fs};
+
+use chip::headless::{self, Limit};
+use chip::launch_options::LaunchOptions;
+use chip::Chip8;
+
+// rom, options, frames, key script (see headless::parse_key_script), golden file
+type TestRom = (
+    &'static str,
+    &'static [&'static str],
+    u64,
+    &'static str,
+    &'static str,
+);
+
+// The Timendus test suite (https://github.com/Timendus/chip8-test-suite) goes in roms/timendus,
+// each rom is run with the quirks it checks. The quirks test asks for the platform first :
+// 1 for CHIP-8, 2 for SUPER-CHIP then 2 for its legacy (1.1) behaviors, 3 for XO-CHIP
+const ROMS: &[TestRom] = &[
+    ("roms/test_opcode.ch8", &[], 300, "", "test_opcode.txt"),
+    (
+        "roms/hires/Hires Test [Tom Swan, 1979].ch8",
+        &[],
+        300,
+        "",
+        "hires_test.txt",
+    ),
+    ("roms/timendus/3-corax+.ch8", &[], 300, "", "corax+.txt"),
+    ("roms/timendus/4-flags.ch8", &[], 300, "", "flags.txt"),
+    (
+        "roms/timendus/5-quirks.ch8",
+        &["--quirks", "vip"],
+        600,
+        "10:+1 15:-1",
+        "quirks_vip.txt",
+    ),
+    (
+        "roms/timendus/5-quirks.ch8",
+        &["--platform", "schip", "--quirks", "schip1.1"],
+        600,
+        "10:+2 15:-2 30:+2 35:-2",
+        "quirks_schip.txt",
+    ),
+    (
+        "roms/timendus/5-quirks.ch8",
+        &["--platform", "xochip", "--quirks", "xochip"],
+        600,
+        "10:+3 15:-3",
+        "quirks_xochip.txt",
+    ),
+];
+
+fn run(rom_path: &str, args: &[&str], frames: u64, keys: &str) -> String {
+    // The goldens don't depend on the rom database, which can be overridden by the user
+    let args = ["--no-database"]
+        .iter()
+        .chain(args)
+        .map(|arg| arg.to_string());
+    let options = LaunchOptions::from_args(args.chain([rom_path.to_string()])).unwrap();
+    let rom = fs::read(rom_path).unwrap();
+    let mut chip8 = Chip8::new(options);
+    chip8.load_rom(&rom).unwrap();
+    let keys = headless::parse_key_script(keys).unwrap();
+    headless::run(&mut chip8, Limit::Frames(frames), &keys).unwrap();
+    chip8.screen().to_text()
+}
+
+fn check(index: usize) {
+    let (rom_path, args, frames, keys, golden) = ROMS[index];
+    let screen = run(rom_path, args, frames, keys);
+    let golden_path = format!("tests/golden/{golden}");
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden_path, &screen).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden_path).unwrap();
+    assert!(
+        screen == expected,
+        "{rom_path} after {frames} frames :\n{screen}\nexpected :\n{expected}"
+    );
+}
+
+#[test]
+fn test_opcode() {
+    check(0);
+}
+
+#[test]
+fn hires_test() {
+    check(1);
+}
+
+#[test]
+#[ignore = "needs roms/timendus/3-corax+.ch8 and its golden"]
+fn timendus_corax_plus() {
+    check(2);
+}
+
+#[test]
+#[ignore = "needs roms/timendus/4-flags.ch8 and its golden"]
+fn timendus_flags() {
+    check(3);
+}
+
+#[test]
+#[ignore = "needs roms/timendus/5-quirks.ch8 and its golden"]
+fn timendus_quirks_cosmac_vip() {
+    check(4);
+}
+
+#[test]
+#[ignore = "needs roms/timendus/5-quirks.ch8 and its golden"]
+fn timendus_quirks_super_chip() {
+    check(5);
+}
+
+#[test]
+#[ignore = "needs roms/timendus/5-quirks.ch8 and its golden"]
+fn timendus_quirks_xo_chip() {
+    check(6);
+}